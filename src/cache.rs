@@ -0,0 +1,366 @@
+use std::{
+  fs, io,
+  path::{Path, PathBuf},
+  time::{Duration, SystemTime},
+};
+
+use anyhow::{bail, Result};
+
+use crate::scripts::{download_lock_path, download_stamp_path};
+
+const QWER_DOWNLOAD_CACHE_MAX_SIZE: &str = "QWER_DOWNLOAD_CACHE_MAX_SIZE";
+const QWER_DOWNLOAD_CACHE_MAX_AGE: &str = "QWER_DOWNLOAD_CACHE_MAX_AGE";
+
+/// Limits for the download cache. Downloads are only kept after an install if at
+/// least one limit is set, or if `--keep-download` was passed.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CacheLimits {
+  pub max_size: Option<u64>,
+  pub max_age: Option<Duration>,
+}
+
+impl CacheLimits {
+  pub fn from_env() -> Result<Self> {
+    let max_size = match std::env::var(QWER_DOWNLOAD_CACHE_MAX_SIZE) {
+      Ok(val) => match parse_size(&val) {
+        Some(size) => Some(size),
+        None => {
+          bail!("Invalid size `{val}` for {QWER_DOWNLOAD_CACHE_MAX_SIZE}, expected e.g. `2G`")
+        }
+      },
+      Err(_) => None,
+    };
+
+    let max_age = match std::env::var(QWER_DOWNLOAD_CACHE_MAX_AGE) {
+      Ok(val) => match humantime::parse_duration(&val) {
+        Ok(age) => Some(age),
+        Err(_) => {
+          bail!("Invalid age `{val}` for {QWER_DOWNLOAD_CACHE_MAX_AGE}, expected e.g. `30days`")
+        }
+      },
+      Err(_) => None,
+    };
+
+    Ok(Self { max_size, max_age })
+  }
+
+  pub fn enabled(&self) -> bool {
+    self.max_size.is_some() || self.max_age.is_some()
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheState {
+  /// The download finished and can be reused.
+  Complete,
+  /// A running qwer process is downloading or installing from it.
+  InUse,
+  /// Left behind by a failed or interrupted download.
+  Incomplete,
+}
+
+#[derive(Debug, Clone)]
+pub struct CacheEntry {
+  pub plugin: String,
+  pub version: String,
+  pub path: PathBuf,
+  pub size: u64,
+  pub used: SystemTime,
+  pub state: CacheState,
+}
+
+/// All download dirs in `downloads_dir`, sorted by plugin and version.
+pub fn entries(downloads_dir: &Path) -> Result<Vec<CacheEntry>> {
+  let mut result = Vec::new();
+
+  for plugin_entry in fs::read_dir(downloads_dir)? {
+    let plugin_dir = plugin_entry?.path();
+    if !plugin_dir.is_dir() {
+      continue;
+    }
+
+    let plugin = plugin_dir
+      .file_name()
+      .unwrap()
+      .to_string_lossy()
+      .to_string();
+    for version_entry in fs::read_dir(&plugin_dir)? {
+      let path = version_entry?.path();
+      let version = path.file_name().unwrap().to_string_lossy().to_string();
+
+      // Stamps and locks live next to the download dirs
+      if version.starts_with('.') || !path.is_dir() {
+        continue;
+      }
+
+      let stamp_path = download_stamp_path(&plugin_dir, &version);
+      let state = if lock_active(&download_lock_path(&plugin_dir, &version)) {
+        CacheState::InUse
+      } else if stamp_path.exists() {
+        CacheState::Complete
+      } else {
+        CacheState::Incomplete
+      };
+
+      let used = fs::metadata(&stamp_path)
+        .or_else(|_| fs::metadata(&path))
+        .and_then(|meta| meta.modified())?;
+
+      result.push(CacheEntry {
+        size: dir_size(&path)?,
+        plugin: plugin.clone(),
+        version,
+        path,
+        used,
+        state,
+      });
+    }
+  }
+
+  result.sort_by(|a, b| (&a.plugin, &a.version).cmp(&(&b.plugin, &b.version)));
+  Ok(result)
+}
+
+/// Pick the entries that have to go to respect `limits`. Incomplete downloads
+/// are always picked, complete ones that are too old next, and after that the
+/// least recently used ones until the rest fits into the size limit.
+pub fn select_prunable<'a>(
+  entries: &'a [CacheEntry],
+  limits: &CacheLimits,
+  now: SystemTime,
+) -> Vec<&'a CacheEntry> {
+  let too_old = |entry: &CacheEntry| match limits.max_age {
+    Some(max_age) => now.duration_since(entry.used).unwrap_or_default() > max_age,
+    None => false,
+  };
+
+  let (mut prunable, mut kept): (Vec<_>, Vec<_>) = entries.iter().partition(|entry| {
+    entry.state == CacheState::Incomplete || (entry.state == CacheState::Complete && too_old(entry))
+  });
+
+  if let Some(max_size) = limits.max_size {
+    // Downloads in use still count towards the limit, but can't be removed
+    kept.sort_by_key(|entry| entry.used);
+    let mut size = kept.iter().map(|entry| entry.size).sum::<u64>();
+    for entry in kept {
+      if size <= max_size {
+        break;
+      }
+
+      if entry.state == CacheState::Complete {
+        size -= entry.size;
+        prunable.push(entry);
+      }
+    }
+  }
+
+  prunable
+}
+
+/// Remove a download dir along with its stamp and lock.
+pub fn remove(entry: &CacheEntry) -> Result<()> {
+  fs::remove_dir_all(&entry.path)?;
+
+  let plugin_dir = entry.path.parent().unwrap();
+  for marker in [
+    download_stamp_path(plugin_dir, &entry.version),
+    download_lock_path(plugin_dir, &entry.version),
+  ] {
+    if marker.exists() {
+      fs::remove_file(marker)?;
+    }
+  }
+
+  Ok(())
+}
+
+/// Remove everything `limits` don't allow anymore, including stamps and locks
+/// whose download dir is gone. Returns the removed entries.
+pub fn prune(downloads_dir: &Path, limits: &CacheLimits) -> Result<Vec<CacheEntry>> {
+  let entries = entries(downloads_dir)?;
+  let prunable = select_prunable(&entries, limits, SystemTime::now());
+  for entry in &prunable {
+    remove(entry)?;
+  }
+
+  prune_orphans(downloads_dir)?;
+  Ok(prunable.into_iter().cloned().collect())
+}
+
+fn prune_orphans(downloads_dir: &Path) -> Result<()> {
+  for plugin_entry in fs::read_dir(downloads_dir)? {
+    let plugin_dir = plugin_entry?.path();
+    if !plugin_dir.is_dir() {
+      continue;
+    }
+
+    for entry in fs::read_dir(&plugin_dir)? {
+      let path = entry?.path();
+      let name = path.file_name().unwrap().to_string_lossy().to_string();
+      let version = match name
+        .strip_prefix('.')
+        .and_then(|name| name.rsplit_once('.'))
+      {
+        Some((version, "downloaded" | "lock")) => version.to_owned(),
+        _ => continue,
+      };
+
+      if !plugin_dir.join(&version).exists()
+        && !lock_active(&download_lock_path(&plugin_dir, &version))
+      {
+        fs::remove_file(path)?;
+      }
+    }
+  }
+
+  Ok(())
+}
+
+/// Check whether the process in the lock at `path` is still running.
+pub fn lock_active(path: &Path) -> bool {
+  let pid = match fs::read_to_string(path) {
+    Ok(contents) => contents.trim().to_owned(),
+    Err(_) => return false,
+  };
+
+  // Parallel installs all run in this process
+  if pid == std::process::id().to_string() {
+    return true;
+  }
+
+  let pid = match pid.parse::<libc::pid_t>() {
+    Ok(pid) if pid > 0 => pid,
+    _ => return false,
+  };
+
+  // Signal 0 only checks whether the process exists. A process of another user,
+  // e.g. when the cache is shared with sudo, can't be signaled but is still alive.
+  if unsafe { libc::kill(pid, 0) } == 0 {
+    return true;
+  }
+
+  io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+/// The size of all files in `path`, without following symlinks.
+pub fn dir_size(path: &Path) -> io::Result<u64> {
+  let meta = fs::symlink_metadata(path)?;
+  if !meta.is_dir() {
+    return Ok(meta.len());
+  }
+
+  let mut size = 0;
+  for entry in fs::read_dir(path)? {
+    size += dir_size(&entry?.path())?;
+  }
+
+  Ok(size)
+}
+
+/// Parse a size like `500M` or `2GiB`. Units are powers of 1024.
+pub fn parse_size(size: &str) -> Option<u64> {
+  let size = size.trim();
+  let split = size
+    .find(|c: char| !c.is_ascii_digit() && c != '.')
+    .unwrap_or(size.len());
+  let (num, unit) = size.split_at(split);
+  let num = num.parse::<f64>().ok()?;
+
+  let exp = match unit.trim().to_ascii_uppercase().as_str() {
+    "" | "B" => 0,
+    "K" | "KB" | "KIB" => 1,
+    "M" | "MB" | "MIB" => 2,
+    "G" | "GB" | "GIB" => 3,
+    "T" | "TB" | "TIB" => 4,
+    _ => return None,
+  };
+
+  Some((num * 1024f64.powi(exp)) as u64)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn parse_sizes() {
+    assert_eq!(parse_size("512"), Some(512));
+    assert_eq!(parse_size("10K"), Some(10 * 1024));
+    assert_eq!(parse_size("500 MB"), Some(500 * 1024 * 1024));
+    assert_eq!(parse_size("1.5GiB"), Some(3 * 512 * 1024 * 1024));
+    assert_eq!(parse_size("2t"), Some(2 * 1024u64.pow(4)));
+    assert_eq!(parse_size("G"), None);
+    assert_eq!(parse_size("10 parsecs"), None);
+  }
+
+  #[test]
+  fn active_locks() {
+    let workdir = tempfile::tempdir().expect("failed to create temp dir");
+    let lock = workdir.as_ref().join("lock");
+    let locked_by = |pid: &str| {
+      fs::write(&lock, pid).expect("failed to write lock");
+      lock_active(&lock)
+    };
+
+    let mut child = std::process::Command::new("true")
+      .spawn()
+      .expect("failed to spawn");
+    let dead_pid = child.id().to_string();
+    child.wait().expect("failed to wait");
+
+    assert!(locked_by(&std::process::id().to_string()));
+    // init is alive, even if it belongs to another user
+    assert!(locked_by("1"));
+    assert!(!locked_by(&dead_pid));
+    assert!(!locked_by("garbage"));
+    assert!(!locked_by("-1"));
+  }
+
+  #[test]
+  fn prune_selection() {
+    let now = SystemTime::now();
+    let days = |days: u64| now - Duration::from_secs(days * 24 * 60 * 60);
+    let entry = |version: &str, size: u64, used: SystemTime, state: CacheState| CacheEntry {
+      plugin: "nodejs".to_owned(),
+      version: version.to_owned(),
+      path: PathBuf::from(version),
+      size,
+      used,
+      state,
+    };
+
+    let entries = vec![
+      entry("14.0.0", 10, days(40), CacheState::Complete),
+      entry("16.0.0", 10, days(20), CacheState::Complete),
+      entry("17.0.0", 10, days(30), CacheState::InUse),
+      entry("18.0.0", 10, days(10), CacheState::Complete),
+      entry("19.0.0", 10, days(1), CacheState::Incomplete),
+      entry("20.0.0", 10, days(0), CacheState::Complete),
+    ];
+
+    let selected = |limits: CacheLimits| {
+      select_prunable(&entries, &limits, now)
+        .into_iter()
+        .map(|entry| entry.version.as_str())
+        .collect::<Vec<_>>()
+    };
+
+    assert_eq!(selected(CacheLimits::default()), vec!["19.0.0"]);
+    assert_eq!(
+      selected(CacheLimits {
+        max_size: None,
+        max_age: Some(Duration::from_secs(25 * 24 * 60 * 60)),
+      }),
+      vec!["14.0.0", "19.0.0"]
+    );
+
+    // The download in use is the oldest, but still has to stay
+    assert_eq!(
+      selected(CacheLimits {
+        max_size: Some(25),
+        max_age: None,
+      }),
+      vec!["19.0.0", "14.0.0", "16.0.0", "18.0.0"]
+    );
+  }
+}
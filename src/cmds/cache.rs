@@ -0,0 +1,149 @@
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use console::style;
+use log::{info, warn};
+use tabled::{object::Segment, Alignment, Modify, Table, Tabled};
+
+use crate::{
+  cache::{self, CacheEntry, CacheLimits, CacheState},
+  dirs::{get_dir, DOWNLOADS_DIR},
+  pretty,
+};
+
+#[derive(Tabled)]
+struct UsageItem {
+  tool: String,
+  downloads: usize,
+  size: String,
+}
+
+pub fn ls() -> Result<()> {
+  let entries = cache::entries(&get_dir(DOWNLOADS_DIR)?)?;
+  if entries.is_empty() {
+    println!("The download cache is empty");
+    return Ok(());
+  }
+
+  let mut usage = BTreeMap::<&str, Vec<&CacheEntry>>::new();
+  for entry in &entries {
+    usage.entry(&entry.plugin).or_default().push(entry);
+  }
+
+  let usage_items = usage.into_iter().map(|(plugin, entries)| UsageItem {
+    tool: pretty::plugin(plugin).to_string(),
+    downloads: entries.len(),
+    size: pretty::size(entries.iter().map(|entry| entry.size).sum()),
+  });
+
+  let table = Table::new(usage_items)
+    .with(tabled::Style::blank())
+    .with(Modify::new(Segment::all()).with(Alignment::left()))
+    .to_string();
+
+  println!("\n{table}");
+
+  let total = entries.iter().map(|entry| entry.size).sum();
+  println!("Total: {}", pretty::size(total));
+
+  let incomplete = entries
+    .iter()
+    .filter(|entry| entry.state == CacheState::Incomplete)
+    .count();
+
+  if incomplete > 0 {
+    println!(
+      "{}",
+      style(format!(
+        "{incomplete} incomplete downloads, run `qwer cache prune` to remove them"
+      ))
+      .yellow()
+    );
+  }
+
+  let limits = CacheLimits::from_env()?;
+  if let Some(max_size) = limits.max_size {
+    println!("Size limit: {}", pretty::size(max_size));
+  }
+
+  if let Some(max_age) = limits.max_age {
+    println!("Age limit: {}", humantime::format_duration(max_age));
+  }
+
+  Ok(())
+}
+
+pub fn clear(name: Option<String>) -> Result<()> {
+  let entries = cache::entries(&get_dir(DOWNLOADS_DIR)?)?;
+  let mut removed = Vec::new();
+
+  for entry in &entries {
+    if name.as_ref().is_some_and(|name| name != &entry.plugin) {
+      continue;
+    }
+
+    if entry.state == CacheState::InUse {
+      warn!(
+        "Skipping {}, it is being used by a running install",
+        pretty::plugin_version(&entry.plugin, &entry.version)
+      );
+      continue;
+    }
+
+    cache::remove(entry)?;
+    removed.push(entry);
+  }
+
+  print_removed(&removed);
+  Ok(())
+}
+
+pub fn prune() -> Result<()> {
+  let limits = CacheLimits::from_env()?;
+  let removed = cache::prune(&get_dir(DOWNLOADS_DIR)?, &limits)?;
+  print_removed(&removed.iter().collect::<Vec<_>>());
+
+  if !limits.enabled() {
+    println!(
+      "{}",
+      style("No cache limits are configured, so only incomplete downloads were removed").dim()
+    );
+  }
+
+  Ok(())
+}
+
+/// Prune the download cache after an install. Errors are only logged, since they
+/// shouldn't change the outcome of the install.
+pub fn auto_prune(limits: &CacheLimits) {
+  let result = get_dir(DOWNLOADS_DIR).and_then(|dir| cache::prune(&dir, limits));
+  match result {
+    Ok(removed) => {
+      for entry in removed {
+        info!(
+          "Removed cached download of {}",
+          pretty::plugin_version(&entry.plugin, &entry.version)
+        );
+      }
+    }
+    Err(err) => warn!("Failed to prune the download cache: {err}"),
+  }
+}
+
+fn print_removed(removed: &[&CacheEntry]) {
+  if removed.is_empty() {
+    println!("Nothing to remove");
+    return;
+  }
+
+  for entry in removed {
+    println!(
+      "Removed {} ({})",
+      pretty::plugin_version(&entry.plugin, &entry.version),
+      pretty::size(entry.size)
+    );
+  }
+
+  let freed = removed.iter().map(|entry| entry.size).sum();
+  println!("Freed {}", pretty::size(freed));
+}
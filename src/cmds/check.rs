@@ -0,0 +1,211 @@
+use std::{
+  collections::{HashMap, HashSet},
+  fs,
+  path::Path,
+};
+
+use anyhow::{bail, Result};
+use console::style;
+use serde::Serialize;
+
+use crate::{
+  dirs::{get_dir, get_plugin_scripts, get_walk_options, PLUGINS_DIR, TOOL_VERSIONS},
+  plugins,
+  versions::{Version, Versions},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Severity {
+  Error,
+  Warning,
+}
+
+#[derive(Debug, Serialize)]
+struct Diagnostic {
+  file: String,
+  line: usize,
+  severity: Severity,
+  message: String,
+}
+
+/// Caches plugin lookups, since the same plugin usually appears in more than one file.
+#[derive(Default)]
+struct Known {
+  plugins: HashMap<String, bool>,
+  versions: HashMap<String, Option<Vec<String>>>,
+}
+
+impl Known {
+  fn plugin(&mut self, name: &str) -> Result<bool> {
+    if let Some(known) = self.plugins.get(name) {
+      return Ok(*known);
+    }
+
+    let installed = get_dir(PLUGINS_DIR)?.join(name).is_dir();
+    let known = installed || plugins::in_registry(name)?;
+    self.plugins.insert(name.to_owned(), known);
+    Ok(known)
+  }
+
+  /// All versions from the plugin's `list-all`, or `None` if the plugin is not installed.
+  fn versions(&mut self, name: &str) -> Result<Option<&Vec<String>>> {
+    if !self.versions.contains_key(name) {
+      let versions = match get_plugin_scripts(name) {
+        Ok(scripts) => Some(scripts.list_all()?),
+        Err(_) => None,
+      };
+
+      self.versions.insert(name.to_owned(), versions);
+    }
+
+    Ok(self.versions[name].as_ref())
+  }
+}
+
+pub fn check(json: bool) -> Result<()> {
+  let versions_files = Versions::find_all_files(
+    std::env::current_dir()?,
+    TOOL_VERSIONS,
+    &get_walk_options()?,
+  )?;
+
+  let mut known = Known::default();
+  let mut diagnostics = Vec::new();
+  for path in &versions_files {
+    check_file(path, &mut known, &mut diagnostics)?;
+  }
+
+  let errors = diagnostics
+    .iter()
+    .filter(|diagnostic| diagnostic.severity == Severity::Error)
+    .count();
+
+  if json {
+    println!("{}", serde_json::to_string_pretty(&diagnostics)?);
+  } else {
+    for diagnostic in &diagnostics {
+      let severity = match diagnostic.severity {
+        Severity::Error => style("error:").bold().red(),
+        Severity::Warning => style("warn:").bold().yellow(),
+      };
+
+      println!(
+        "{}:{}: {} {}",
+        style(&diagnostic.file).bold(),
+        diagnostic.line,
+        severity,
+        diagnostic.message
+      );
+    }
+
+    if diagnostics.is_empty() {
+      println!(
+        "Checked {} versions files, no problems found",
+        versions_files.len()
+      );
+    }
+  }
+
+  if errors > 0 {
+    bail!("Found {errors} errors in versions files");
+  }
+
+  Ok(())
+}
+
+fn check_file(path: &Path, known: &mut Known, diagnostics: &mut Vec<Diagnostic>) -> Result<()> {
+  let file = path.to_string_lossy().to_string();
+  let content = fs::read_to_string(path)?;
+  let mut seen = HashSet::new();
+
+  let mut report = |line: usize, severity: Severity, message: String| {
+    diagnostics.push(Diagnostic {
+      file: file.clone(),
+      line,
+      severity,
+      message,
+    })
+  };
+
+  for (line, entry) in Versions::parse_lines(&content) {
+    let (plugin, versions) = match entry {
+      Ok(entry) => entry,
+      Err(err) => {
+        report(line, Severity::Error, err.to_string());
+        continue;
+      }
+    };
+
+    if !seen.insert(plugin.clone()) {
+      report(
+        line,
+        Severity::Error,
+        format!("version for `{plugin}` appeared twice"),
+      );
+      continue;
+    }
+
+    if versions.iter().any(|version| version.raw().is_empty()) {
+      report(
+        line,
+        Severity::Error,
+        format!("entry for `{plugin}` contains an empty version, check for repeated spaces"),
+      );
+      continue;
+    }
+
+    if !known.plugin(&plugin)? {
+      report(
+        line,
+        Severity::Error,
+        format!("plugin `{plugin}` is not installed and not in any registry"),
+      );
+      continue;
+    }
+
+    let available = match known.versions(&plugin)? {
+      Some(available) => available,
+      None => {
+        report(
+          line,
+          Severity::Warning,
+          format!("plugin `{plugin}` is not installed, its versions were not checked"),
+        );
+        continue;
+      }
+    };
+
+    for version in &versions {
+      let version_str = match version {
+        Version::Remote(version_str) => version_str,
+        _ => continue,
+      };
+
+      if matches!(version_str.as_str(), "latest" | "latest-stable") {
+        continue;
+      }
+
+      if !available.contains(version_str) {
+        report(
+          line,
+          Severity::Error,
+          format!("version `{version_str}` is not known to plugin `{plugin}`"),
+        );
+        continue;
+      }
+
+      if get_plugin_scripts(&plugin)?.version_incomplete(version) {
+        report(
+          line,
+          Severity::Warning,
+          format!(
+            "install of `{plugin}` `{version_str}` did not finish, run `qwer install {plugin} {version_str}` to try again"
+          ),
+        );
+      }
+    }
+  }
+
+  Ok(())
+}
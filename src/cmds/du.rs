@@ -0,0 +1,207 @@
+use std::{
+  collections::{BTreeMap, HashSet},
+  fs,
+  path::{Path, PathBuf},
+  sync::mpsc,
+  thread,
+};
+
+use anyhow::Result;
+use console::{pad_str, style, Alignment};
+use log::warn;
+use threadpool::ThreadPool;
+
+use crate::{
+  cache::dir_size,
+  cmds::env::{current_env, resolve_target_env},
+  dirs::{get_dir, DOWNLOADS_DIR, INSTALLS_DIR, PLUGINS_DIR},
+  pretty,
+  scripts::install_complete,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Kind {
+  Plugin,
+  Install,
+  Download,
+}
+
+#[derive(Debug)]
+struct Usage {
+  plugin: String,
+  kind: Kind,
+  version: Option<String>,
+  path: PathBuf,
+  size: u64,
+}
+
+pub fn du() -> Result<()> {
+  let installs_dir = get_dir(INSTALLS_DIR)?;
+  let mut usages = Vec::new();
+
+  for entry in fs::read_dir(get_dir(PLUGINS_DIR)?)? {
+    let path = entry?.path();
+    usages.push(Usage {
+      plugin: file_name(&path),
+      kind: Kind::Plugin,
+      version: None,
+      path,
+      size: 0,
+    });
+  }
+
+  for (dir, kind) in [
+    (installs_dir.clone(), Kind::Install),
+    (get_dir(DOWNLOADS_DIR)?, Kind::Download),
+  ] {
+    for plugin_entry in fs::read_dir(dir)? {
+      let plugin_dir = plugin_entry?.path();
+      if !plugin_dir.is_dir() {
+        continue;
+      }
+
+      for version_entry in fs::read_dir(&plugin_dir)? {
+        let path = version_entry?.path();
+        let version = file_name(&path);
+
+        // Markers and stamps live next to the versions
+        if version.starts_with('.') || !path.is_dir() {
+          continue;
+        }
+
+        usages.push(Usage {
+          plugin: file_name(&plugin_dir),
+          kind,
+          version: Some(version),
+          path,
+          size: 0,
+        });
+      }
+    }
+  }
+
+  let usages = measure(usages);
+  let in_use = in_use_versions(&installs_dir);
+
+  let mut by_plugin = BTreeMap::<&str, Vec<&Usage>>::new();
+  for usage in &usages {
+    by_plugin.entry(&usage.plugin).or_default().push(usage);
+  }
+
+  if by_plugin.is_empty() {
+    println!("Nothing installed");
+    return Ok(());
+  }
+
+  let total_size = |usages: &[&Usage]| usages.iter().map(|usage| usage.size).sum::<u64>();
+  let mut by_plugin = by_plugin.into_iter().collect::<Vec<_>>();
+  by_plugin.sort_by_key(|(_, usages)| std::cmp::Reverse(total_size(usages)));
+
+  for (plugin, mut usages) in by_plugin {
+    // Padding has to skip the color codes
+    println!(
+      "{} {:>10}",
+      pad_str(
+        &pretty::plugin(plugin).to_string(),
+        28,
+        Alignment::Left,
+        None
+      ),
+      pretty::size(total_size(&usages))
+    );
+
+    usages.sort_by_key(|usage| (usage.kind, std::cmp::Reverse(usage.size)));
+    for usage in usages {
+      let version = usage.version.as_deref().unwrap_or_default();
+      let (label, note) = match usage.kind {
+        Kind::Plugin => ("plugin".to_owned(), None),
+        Kind::Download => (format!("{version} (download)"), None),
+        Kind::Install if !install_complete(installs_dir.join(plugin), version) => {
+          (version.to_owned(), Some(style("incomplete").yellow()))
+        }
+        Kind::Install if in_use.contains(&(plugin.to_owned(), version.to_owned())) => {
+          (version.to_owned(), Some(style("in use").green()))
+        }
+        Kind::Install => (version.to_owned(), None),
+      };
+
+      match note {
+        Some(note) => println!("  {label:<26} {:>10}  {note}", pretty::size(usage.size)),
+        None => println!("  {label:<26} {:>10}", pretty::size(usage.size)),
+      }
+    }
+  }
+
+  println!(
+    "\nTotal: {}",
+    pretty::size(usages.iter().map(|usage| usage.size).sum())
+  );
+
+  Ok(())
+}
+
+/// Measure the size of every entry. Installs can be huge trees, so they are
+/// walked in parallel.
+fn measure(usages: Vec<Usage>) -> Vec<Usage> {
+  let jobs = thread::available_parallelism()
+    .map(|num| num.get())
+    .unwrap_or(1);
+
+  let pool = ThreadPool::new(jobs);
+  let (sender, receiver) = mpsc::channel();
+  for mut usage in usages {
+    let sender = sender.clone();
+    pool.execute(move || {
+      match dir_size(&usage.path) {
+        Ok(size) => usage.size = size,
+        Err(err) => warn!("Failed to measure {}: {err}", usage.path.to_string_lossy()),
+      }
+
+      let _ = sender.send(usage);
+    });
+  }
+
+  drop(sender);
+  receiver.into_iter().collect()
+}
+
+/// Versions that the hook activated in this shell, and the ones that apply in the
+/// current directory.
+fn in_use_versions(installs_dir: &Path) -> HashSet<(String, String)> {
+  let mut result = HashSet::new();
+
+  // The hook only stores the resulting env, but the bin paths of a version are
+  // inside its install dir
+  for entry in current_env().map(|env| env.path).unwrap_or_default() {
+    if let Ok(rest) = Path::new(&entry).strip_prefix(installs_dir) {
+      let mut parts = rest.iter();
+      if let (Some(plugin), Some(version)) = (parts.next(), parts.next()) {
+        result.insert((
+          plugin.to_string_lossy().to_string(),
+          version.to_string_lossy().to_string(),
+        ));
+      }
+    }
+  }
+
+  let target = std::env::current_dir()
+    .map_err(anyhow::Error::from)
+    .and_then(|dir| resolve_target_env(&dir));
+
+  if let Ok(target) = target {
+    for (tool, version) in target.tools {
+      if let Some(version) = version {
+        result.insert((tool.plugin, version.version_str().to_owned()));
+      }
+    }
+  }
+
+  result
+}
+
+fn file_name(path: &Path) -> String {
+  path
+    .file_name()
+    .map(|name| name.to_string_lossy().to_string())
+    .unwrap_or_default()
+}
@@ -1,22 +1,66 @@
+use std::{
+  cell::RefCell,
+  collections::{BTreeMap, HashMap},
+  ffi::{OsStr, OsString},
+  fs,
+  path::{Path, PathBuf},
+};
+
 use anyhow::{bail, Result};
-use log::trace;
+use console::style;
+use log::{trace, warn};
+use serde::Serialize;
+use tabled::{object::Segment, Alignment, Modify, Table, Tabled};
 
 use crate::{
-  dirs::{get_dir, get_plugin_scripts, INSTALLS_DIR, TOOL_VERSIONS},
+  cmds::{projects::record_projects, util::resolve_dir},
+  dirs::{
+    get_data_dir, get_dir, get_home_tool_versions, get_plugin_scripts, get_walk_options,
+    get_xdg_tool_versions, DOTENV, INSTALLS_DIR, PLUGINS_DIR, PROJECT_CONFIG, TOOL_VERSIONS,
+    TRUST_STORE,
+  },
+  dotenv::find_dotenv_files,
   env::Env,
+  project::find_project_envs,
+  scripts::{install_complete, used_stamp_path},
   shell::ShellState,
-  versions::{Version, Versions},
+  trust::{TrustStatus, TrustStore},
+  versions::{version_override_var, ToolEntry, Version, Versions, WalkOptions},
 };
 
 const QWER_STATE: &str = "QWER_STATE";
 const QWER_PREV: &str = "QWER_PREV";
 const QWER_CURRENT: &str = "QWER_CURRENT";
+const QWER_LOAD_DOTENV: &str = "QWER_LOAD_DOTENV";
+const QWER_PURE_PATH: &str = "QWER_PURE_PATH";
+
+/// System path entries that stay available in pure mode, unless configured
+/// otherwise with `QWER_PURE_PATH`.
+const DEFAULT_PURE_PATH: &str = "/usr/bin:/bin";
+
+/// Vars that are kept when starting from a cleared env, since a lot of programs
+/// don't work without them.
+const CLEAR_ENV_PASSTHROUGH: &[&str] = &["HOME", "USER", "LOGNAME", "LANG", "TERM", "TMPDIR"];
 
 pub fn update_env() -> Result<ShellState> {
   let mut state = ShellState::new();
-
-  match get_target_env()? {
-    Some(target_env) => apply_target_env(&mut state, &target_env),
+  let target = resolve_target_env(&std::env::current_dir()?)?;
+
+  match non_empty(target.combined()) {
+    Some(target_env) => {
+      // The hook runs on every prompt, so this only happens when the env changes
+      if apply_target_env(&mut state, &target_env) {
+        mark_used(&target);
+
+        let mut sources = target
+          .entries
+          .iter()
+          .map(|entry| entry.source.clone())
+          .collect::<Vec<_>>();
+        sources.dedup();
+        record_projects(&sources);
+      }
+    }
     None => {
       revert_current_env(&mut state);
       clear_state_vars(&mut state);
@@ -26,7 +70,47 @@ pub fn update_env() -> Result<ShellState> {
   Ok(state)
 }
 
-fn apply_target_env(state: &mut ShellState, target_env: &Env) {
+/// Record that the versions in `target` were just activated, so pruning knows
+/// they are still in use.
+pub fn mark_used(target: &TargetEnv) {
+  let installs_dir = match get_dir(INSTALLS_DIR) {
+    Ok(installs_dir) => installs_dir,
+    Err(_) => return,
+  };
+
+  for (tool, version) in &target.tools {
+    if let Some(version) = version {
+      let stamp_path = used_stamp_path(installs_dir.join(&tool.plugin), version.version_str());
+      if let Err(err) = fs::write(&stamp_path, "") {
+        trace!("Failed to write {stamp_path:?}: {err}");
+      }
+    }
+  }
+}
+
+/// Like [`update_env`], but the path only consists of the target env's entries and
+/// the pure path allowlist. The result isn't tracked, so it is meant for shells
+/// that don't run the hook, like CI jobs.
+pub fn pure_env() -> Result<ShellState> {
+  let mut state = ShellState::new();
+  revert_current_env(&mut state);
+  clear_state_vars(&mut state);
+
+  let target_env = get_target_env(&std::env::current_dir()?)?.unwrap_or_default();
+  for (key, val) in &target_env.vars {
+    state.set(key, val);
+  }
+
+  state.replace_path(get_pure_path(
+    &target_env,
+    &std::env::var(QWER_PURE_PATH).unwrap_or_else(|_| DEFAULT_PURE_PATH.to_owned()),
+  ));
+
+  Ok(state)
+}
+
+/// Apply `target_env` to `state`, returning whether anything changed.
+fn apply_target_env(state: &mut ShellState, target_env: &Env) -> bool {
   let target_env_hash = format!("{}", target_env.hash());
   let current_env_hash = std::env::var(QWER_STATE).ok();
   let changed = current_env_hash
@@ -38,7 +122,8 @@ fn apply_target_env(state: &mut ShellState, target_env: &Env) {
 
   if !changed {
     trace!("Env did not change");
-    return;
+    migrate_state_vars(state);
+    return false;
   }
 
   // Env was changed, update it
@@ -74,6 +159,8 @@ fn apply_target_env(state: &mut ShellState, target_env: &Env) {
   for entry in &target_env.path {
     state.add_path(entry);
   }
+
+  true
 }
 
 fn revert_current_env(state: &mut ShellState) {
@@ -90,7 +177,13 @@ fn revert_current_env(state: &mut ShellState) {
   }
 
   // Unset the current vars
-  let current = Env::deserialize(&current.unwrap()).unwrap_or_default();
+  let current = match Env::deserialize(&current.unwrap()) {
+    Ok(current) => current,
+    Err(err) => {
+      warn!("Failed to read the env set by qwer, open a new shell to reset it: {err}");
+      Env::default()
+    }
+  };
   state.revert(&current);
 
   if let Ok(prev_env) = std::env::var(QWER_PREV) {
@@ -100,30 +193,166 @@ fn revert_current_env(state: &mut ShellState) {
   }
 }
 
+/// The env the shell hook applied in this shell, if any.
+pub fn current_env() -> Option<Env> {
+  let current = std::env::var(QWER_CURRENT).ok()?;
+  Env::deserialize(&current).ok()
+}
+
+/// Rewrite state vars that were set by an older version of qwer, so shells that
+/// were started before an update keep working once support for the old format is
+/// dropped.
+fn migrate_state_vars(state: &mut ShellState) {
+  for key in [QWER_CURRENT, QWER_PREV] {
+    let value = match std::env::var(key) {
+      Ok(value) if Env::is_legacy(&value) => value,
+      _ => continue,
+    };
+
+    if let Ok(env) = Env::deserialize(&value) {
+      trace!("Migrating {key} to the current format");
+      state.set(key, &env.serialize());
+    }
+  }
+}
+
 fn clear_state_vars(state: &mut ShellState) {
   state.unset(QWER_STATE);
   state.unset(QWER_PREV);
   state.unset(QWER_CURRENT);
 }
 
-fn get_target_env() -> Result<Option<Env>> {
-  trace!("Getting current env");
-  let versions = get_combined_versions()?;
-  if versions.is_none() {
-    return Ok(None);
+/// Everything that contributes to the env of a directory, by where it came from.
+#[derive(Debug, Default)]
+pub struct TargetEnv {
+  /// Tool entries of all trusted versions files in order of precedence, including
+  /// the ones that are overridden by closer files.
+  pub entries: Vec<ToolEntry>,
+  /// The tools in use and the installed version chosen for each, `None` if none
+  /// of the version options are installed.
+  pub tools: Vec<(ToolEntry, Option<Version>)>,
+  /// The parts of the env in order of precedence. Path entries of earlier layers
+  /// come first, and vars of earlier layers win.
+  pub layers: Vec<EnvLayer>,
+}
+
+#[derive(Debug)]
+pub struct EnvLayer {
+  pub origin: EnvOrigin,
+  pub env: Env,
+  /// The line each var was defined on, if it is known.
+  pub lines: BTreeMap<String, usize>,
+}
+
+#[derive(Debug, Clone)]
+pub enum EnvOrigin {
+  Project(PathBuf),
+  Dotenv(PathBuf),
+  Tool {
+    entry: ToolEntry,
+    version: Version,
+    script: &'static str,
+  },
+}
+
+impl EnvOrigin {
+  pub fn title(&self) -> String {
+    match self {
+      Self::Project(path) | Self::Dotenv(path) => path.to_string_lossy().to_string(),
+      Self::Tool { entry, version, .. } => format!("{} {}", entry.plugin, version.raw()),
+    }
   }
 
-  let versions = versions.unwrap();
+  pub fn script(&self) -> Option<&'static str> {
+    match self {
+      Self::Tool { script, .. } => Some(script),
+      _ => None,
+    }
+  }
+}
+
+impl TargetEnv {
+  /// Combine all layers into the env that gets applied.
+  pub fn combined(&self) -> Env {
+    let mut env = Env::default();
+    for layer in &self.layers {
+      env.merge_lower(layer.env.clone());
+    }
+
+    env
+  }
+}
+
+/// Compute the env that the tools, project configs and dotenv files in effect for
+/// `dir` want to set.
+pub fn get_target_env(dir: &Path) -> Result<Option<Env>> {
+  Ok(non_empty(resolve_target_env(dir)?.combined()))
+}
+
+fn non_empty(env: Env) -> Option<Env> {
+  if env.vars.is_empty() && env.path.is_empty() {
+    None
+  } else {
+    Some(env)
+  }
+}
+
+pub fn resolve_target_env(dir: &Path) -> Result<TargetEnv> {
+  trace!("Getting env for {dir:?}");
+  let trust = TrustGate::load()?;
+  let mut target = TargetEnv::default();
+
+  // Project settings are explicit, so they win over dotenv files and anything
+  // the tools set. The global versions files are not project configs or dotenv
+  // files, so we don't use them here.
+  let walk_options = WalkOptions::from_env();
+  for (path, env, lines) in find_project_envs(dir, PROJECT_CONFIG, &walk_options, |path| {
+    trust.allows(path)
+  })? {
+    target.layers.push(EnvLayer {
+      origin: EnvOrigin::Project(path),
+      env,
+      lines,
+    });
+  }
+
+  if load_dotenv() {
+    for (path, vars) in find_dotenv_files(dir, DOTENV, &walk_options, |path| trust.allows(path))? {
+      let mut layer = EnvLayer {
+        origin: EnvOrigin::Dotenv(path),
+        env: Env::default(),
+        lines: BTreeMap::new(),
+      };
+
+      for (line, key, val) in vars {
+        layer.lines.insert(key.clone(), line);
+        layer.env.vars.insert(key, val);
+      }
+
+      target.layers.push(layer);
+    }
+  }
+
+  target.entries = tool_entries(dir, &trust)?;
+
   let installs_dir = get_dir(INSTALLS_DIR)?;
-  let mut env = Env::default();
 
-  for (plugin, version_opts) in versions.iter() {
+  // The entries are ordered by precedence, so the first entry of every tool is the
+  // one in use, and tools that come first also come first in the resulting env.
+  for tool in &target.entries {
+    let plugin = &tool.plugin;
+    if target.tools.iter().any(|(used, _)| used.plugin == *plugin) {
+      continue;
+    }
+
+    let version_opts = &tool.versions;
     trace!("Finding version for plugin `{plugin}`, options: `{version_opts:?}`");
     let install_dir = installs_dir.join(plugin);
     let found = version_opts
       .iter()
-      .find(|version| install_dir.join(version.version_str()).is_dir());
+      .find(|version| install_complete(&install_dir, version.version_str()));
 
+    target.tools.push((tool.clone(), found.cloned()));
     if found.is_none() {
       trace!("No version found for `{plugin}`");
       continue;
@@ -132,26 +361,413 @@ fn get_target_env() -> Result<Option<Env>> {
     trace!("Version `{found:?}` found");
     let scripts = get_plugin_scripts(plugin)?;
     let version = found.unwrap();
-    env.merge(scripts.get_env(version)?);
+    let version_env = scripts.get_version_env(version)?;
+
+    for (script, env) in [
+      ("exec-env", version_env.exec_env),
+      ("list-bin-paths", version_env.bin_paths),
+    ] {
+      target.layers.push(EnvLayer {
+        origin: EnvOrigin::Tool {
+          entry: tool.clone(),
+          version: version.clone(),
+          script,
+        },
+        env,
+        lines: BTreeMap::new(),
+      });
+    }
   }
 
-  if env.vars.is_empty() && env.path.is_empty() {
-    Ok(None)
-  } else {
-    Ok(Some(env))
+  Ok(target)
+}
+
+/// Build the variables for a child process that should run in `target_env`. Whatever
+/// the shell hook applied is reverted first, so the result only depends on the
+/// target env and not on the directory the shell happens to be in. Vars that
+/// aren't valid UTF-8 are passed through untouched.
+pub fn get_process_env(target_env: &Env) -> HashMap<OsString, OsString> {
+  process_env(std::env::vars_os().collect(), target_env)
+}
+
+fn process_env(
+  mut vars: HashMap<OsString, OsString>,
+  target_env: &Env,
+) -> HashMap<OsString, OsString> {
+  let mut stale_path = Vec::new();
+
+  if let Some(current) = vars
+    .get(OsStr::new(QWER_CURRENT))
+    .and_then(|val| val.to_str())
+  {
+    let current = Env::deserialize(current).unwrap_or_default();
+    for key in current.vars.keys() {
+      vars.remove(OsStr::new(key));
+    }
+
+    stale_path = current.path;
+  }
+
+  if let Some(prev) = vars.get(OsStr::new(QWER_PREV)).and_then(|val| val.to_str()) {
+    if let Ok(prev) = Env::deserialize(prev) {
+      vars.extend(
+        prev
+          .vars
+          .into_iter()
+          .map(|(key, val)| (key.into(), val.into())),
+      );
+    }
   }
+
+  for key in [QWER_STATE, QWER_PREV, QWER_CURRENT] {
+    vars.remove(OsStr::new(key));
+  }
+
+  vars.extend(
+    target_env
+      .vars
+      .iter()
+      .map(|(key, val)| (key.into(), val.into())),
+  );
+
+  let inherited_path = vars.remove(OsStr::new("PATH")).unwrap_or_default();
+  let inherited_path = std::env::split_paths(&inherited_path).filter(|entry| {
+    !entry.as_os_str().is_empty()
+      && !stale_path.iter().any(|stale| Path::new(stale) == entry)
+      && !target_env
+        .path
+        .iter()
+        .any(|target| Path::new(target) == entry)
+  });
+
+  let mut path = OsString::new();
+  for entry in target_env
+    .path
+    .iter()
+    .map(PathBuf::from)
+    .chain(inherited_path)
+  {
+    if !path.is_empty() {
+      path.push(":");
+    }
+
+    path.push(entry);
+  }
+
+  vars.insert("PATH".into(), path);
+  vars
 }
 
-pub fn current(name: String) -> Result<()> {
-  if let Some(current) = find_current_version(&name)? {
-    println!("{} {}", name, current.raw());
+/// Same as [`get_process_env`], but the path only consists of the target env's
+/// entries and the pure path allowlist. With `clear_env`, all other vars except a
+/// few basic ones are dropped as well.
+pub fn get_pure_process_env(target_env: &Env, clear_env: bool) -> HashMap<OsString, OsString> {
+  let mut vars = if clear_env {
+    let mut vars = CLEAR_ENV_PASSTHROUGH
+      .iter()
+      .filter_map(|key| Some((key.into(), std::env::var_os(key)?)))
+      .collect::<HashMap<_, _>>();
+
+    vars.extend(
+      target_env
+        .vars
+        .iter()
+        .map(|(key, val)| (key.into(), val.into())),
+    );
+    vars
   } else {
-    println!("No version in use for {}", name);
+    get_process_env(target_env)
+  };
+
+  let allowlist = std::env::var(QWER_PURE_PATH).unwrap_or_else(|_| DEFAULT_PURE_PATH.to_owned());
+  vars.insert(
+    "PATH".into(),
+    get_pure_path(target_env, &allowlist).join(":").into(),
+  );
+
+  vars
+}
+
+/// Build the path for pure mode from the target env's entries, followed by the
+/// `:` separated entries of `allowlist`.
+fn get_pure_path(target_env: &Env, allowlist: &str) -> Vec<String> {
+  let mut path = Env::default();
+  for entry in target_env
+    .path
+    .iter()
+    .map(|entry| entry.as_str())
+    .chain(allowlist.split(':'))
+  {
+    if !entry.is_empty() {
+      path.add_path(entry);
+    }
+  }
+
+  path.path
+}
+
+/// Every tool entry in effect for `dir` in order of precedence. Override vars come
+/// first, followed by the entries of the trusted versions files.
+fn tool_entries(dir: &Path, trust: &TrustGate) -> Result<Vec<ToolEntry>> {
+  let plugins_dir = get_dir(PLUGINS_DIR)?;
+  let mut plugins = match fs::read_dir(&plugins_dir) {
+    Ok(entries) => entries
+      .filter_map(|entry| entry.ok())
+      .map(|entry| entry.file_name().to_string_lossy().to_string())
+      .collect::<Vec<_>>(),
+    Err(_) => Vec::new(),
+  };
+  plugins.sort();
+
+  let mut entries = override_entries(&plugins, |var| std::env::var(var).ok());
+  entries.extend(trusted_entries(
+    dir,
+    TOOL_VERSIONS,
+    &get_walk_options()?,
+    |path| trust.allows(path),
+  )?);
+
+  Ok(entries)
+}
+
+/// The tools of `plugins` whose override var is set. The vars come from the user's
+/// own shell, so they don't need to be trusted.
+fn override_entries(plugins: &[String], var: impl Fn(&str) -> Option<String>) -> Vec<ToolEntry> {
+  plugins
+    .iter()
+    .filter_map(|plugin| {
+      let versions = var(&version_override_var(plugin))?
+        .split_whitespace()
+        .map(Version::parse)
+        .collect::<Vec<_>>();
+
+      if versions.is_empty() {
+        return None;
+      }
+
+      Some(ToolEntry::from_override(plugin, versions))
+    })
+    .collect()
+}
+
+/// The entries of all versions files found from `dir`, skipping files rejected by
+/// `allows`. Files are filtered before tools defined more than once are resolved,
+/// so an untrusted file can't hide the version a trusted file further up pins.
+fn trusted_entries(
+  dir: &Path,
+  filename: &str,
+  options: &WalkOptions,
+  allows: impl Fn(&Path) -> bool,
+) -> Result<Vec<ToolEntry>> {
+  Ok(
+    Versions::find_all_entries(dir, filename, options)?
+      .into_iter()
+      .filter(|entry| allows(&entry.source))
+      .collect(),
+  )
+}
+
+/// Decides which files are allowed to change the env. The global versions files
+/// belong to the user and are always allowed, anything else needs `qwer trust`.
+struct TrustGate {
+  store: TrustStore,
+  implicit: Vec<PathBuf>,
+  checked: RefCell<HashMap<PathBuf, bool>>,
+}
+
+impl TrustGate {
+  fn load() -> Result<Self> {
+    Ok(Self {
+      store: TrustStore::load(get_data_dir()?.join(TRUST_STORE))?,
+      implicit: vec![get_home_tool_versions()?, get_xdg_tool_versions()?],
+      checked: RefCell::new(HashMap::new()),
+    })
+  }
+
+  /// Check whether a file is trusted, warning once for every file that isn't.
+  fn allows(&self, path: &Path) -> bool {
+    if self.implicit.iter().any(|implicit| implicit == path) {
+      return true;
+    }
+
+    if let Some(allowed) = self.checked.borrow().get(path) {
+      return *allowed;
+    }
+
+    let path_str = path.to_string_lossy();
+    let allowed = match self.store.status(path) {
+      Ok(TrustStatus::Trusted) => true,
+      Ok(TrustStatus::Untrusted) => {
+        warn!("{path_str} is not trusted, run `qwer trust` to allow it");
+        false
+      }
+      Ok(TrustStatus::Changed) => {
+        warn!("{path_str} changed since it was trusted, run `qwer trust` to allow it again");
+        false
+      }
+      Err(err) => {
+        warn!("Failed to check if {path_str} is trusted: {err}");
+        false
+      }
+    };
+
+    self.checked.borrow_mut().insert(path.to_owned(), allowed);
+    allowed
+  }
+}
+
+fn load_dotenv() -> bool {
+  std::env::var(QWER_LOAD_DOTENV)
+    .map(|val| matches!(val.as_str(), "1" | "true" | "yes"))
+    .unwrap_or(false)
+}
+
+pub fn env(dir: Option<String>) -> Result<()> {
+  let dir = resolve_dir(dir)?;
+  let target = resolve_target_env(&dir)?;
+  let mut printed = None;
+
+  for layer in &target.layers {
+    if layer.env.vars.is_empty() && layer.env.path.is_empty() {
+      continue;
+    }
+
+    let title = layer.origin.title();
+    if printed.as_ref() != Some(&title) {
+      println!("{}", style(&title).bold());
+      printed = Some(title);
+    }
+
+    let indent = match layer.origin.script() {
+      Some(script) => {
+        println!("  {}", style(script).dim());
+        "    "
+      }
+      None => "  ",
+    };
+
+    for (key, val) in &layer.env.vars {
+      println!("{indent}{key}={val}");
+    }
+
+    for entry in &layer.env.path {
+      println!("{indent}{} {entry}", style("PATH").dim());
+    }
+  }
+
+  if printed.is_none() {
+    println!("No env for {}", dir.to_string_lossy());
   }
 
   Ok(())
 }
 
+#[derive(Debug, Serialize)]
+struct CurrentEntry {
+  name: String,
+  version: String,
+  installed: bool,
+  source: String,
+}
+
+#[derive(Tabled)]
+struct CurrentItem {
+  name: String,
+  version: String,
+  installed: String,
+  source: String,
+}
+
+pub fn current(name: Option<String>, json: bool) -> Result<()> {
+  let entries = get_current_entries()?;
+
+  if let Some(name) = name {
+    let entry = entries.into_iter().find(|entry| entry.name == name);
+    if json {
+      println!("{}", serde_json::to_string_pretty(&entry)?);
+      return Ok(());
+    }
+
+    match entry {
+      Some(entry) if entry.installed => println!("{} {}", name, entry.version),
+      _ => println!("No version in use for {}", name),
+    }
+
+    return Ok(());
+  }
+
+  if json {
+    println!("{}", serde_json::to_string_pretty(&entries)?);
+    return Ok(());
+  }
+
+  if entries.is_empty() {
+    println!("No tools in use");
+    return Ok(());
+  }
+
+  let items = entries.into_iter().map(|entry| CurrentItem {
+    name: entry.name,
+    version: style(entry.version).cyan().to_string(),
+    installed: if entry.installed {
+      style("yes").green().to_string()
+    } else {
+      style("no").red().to_string()
+    },
+    source: style(entry.source).dim().to_string(),
+  });
+
+  let table = Table::new(items)
+    .with(tabled::Style::blank())
+    .with(Modify::new(Segment::all()).with(Alignment::left()))
+    .to_string();
+
+  println!("\n{table}");
+  Ok(())
+}
+
+/// Collect every tool in effect for the current directory, the same way the shell
+/// hook does. The version shown is the first installed option, or the first option
+/// if none of them are installed.
+fn get_current_entries() -> Result<Vec<CurrentEntry>> {
+  let trust = TrustGate::load()?;
+  let entries = tool_entries(&std::env::current_dir()?, &trust)?;
+
+  let installs_dir = get_dir(INSTALLS_DIR)?;
+  let mut result = Vec::<CurrentEntry>::new();
+  for tool in entries {
+    // Entries are ordered by precedence, so the first one of every tool is in use
+    if result.iter().any(|entry| entry.name == tool.plugin) {
+      continue;
+    }
+
+    let install_dir = installs_dir.join(&tool.plugin);
+    let found = tool
+      .versions
+      .iter()
+      .find(|version| install_complete(&install_dir, version.version_str()));
+
+    let (version, installed) = match found {
+      Some(found) => (found, true),
+      None => (tool.versions.first().unwrap(), false),
+    };
+
+    result.push(CurrentEntry {
+      name: tool.plugin.clone(),
+      version: version.raw(),
+      installed,
+      source: if tool.is_override() {
+        format!("{} (override)", tool.location())
+      } else {
+        tool.location()
+      },
+    });
+  }
+
+  result.sort_by(|a, b| a.name.cmp(&b.name));
+  Ok(result)
+}
+
 pub fn wwhere(name: String, version: Option<String>) -> Result<()> {
   let scripts = get_plugin_scripts(&name)?;
   if !scripts.plugin_installed() {
@@ -177,39 +793,104 @@ pub fn wwhere(name: String, version: Option<String>) -> Result<()> {
 }
 
 fn find_current_version(name: &str) -> Result<Option<Version>> {
-  let versions = get_combined_versions()?;
-  if versions.is_none() {
-    return Ok(None);
-  }
+  Ok(
+    get_current_entries()?
+      .into_iter()
+      .find(|entry| entry.name == name && entry.installed)
+      .map(|entry| Version::parse(&entry.version)),
+  )
+}
 
-  let versions = versions.unwrap();
-  let found_plugin = versions.get(name);
-  if found_plugin.is_none() {
-    return Ok(None);
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn pure_path() {
+    let mut env = Env::default();
+    env.add_path("/tool/bin");
+    env.add_path("/usr/bin");
+
+    assert_eq!(
+      get_pure_path(&env, "/usr/bin::/bin"),
+      vec!["/tool/bin", "/usr/bin", "/bin"]
+    );
+    assert_eq!(get_pure_path(&Env::default(), ""), Vec::<String>::new());
   }
 
-  let found_plugin = found_plugin.unwrap();
-  let installs_dir = get_dir(INSTALLS_DIR)?;
-  let install_dir = installs_dir.join(name);
-
-  let found_install = found_plugin
-    .iter()
-    .find(|version| install_dir.join(version.version_str()).is_dir());
-
-  Ok(found_install.map(|found| found.to_owned()))
-}
-
-fn get_combined_versions() -> Result<Option<Versions>> {
-  let versions_files = Versions::find_all(std::env::current_dir()?, TOOL_VERSIONS)?;
-  if versions_files.is_empty() {
-    trace!("Empty versions file found");
-    return Ok(None);
+  #[test]
+  fn process_env_keeps_non_utf8_vars() {
+    use std::os::unix::ffi::OsStringExt;
+
+    let invalid = OsString::from_vec(vec![b'a', 0xff, b'b']);
+    let mut current = Env::default();
+    current.add_path("/stale/bin");
+    current.vars.insert("STALE".to_owned(), "1".to_owned());
+
+    let vars = HashMap::from([
+      ("INVALID".into(), invalid.clone()),
+      ("STALE".into(), "1".into()),
+      (QWER_CURRENT.into(), current.serialize().into()),
+      (
+        "PATH".into(),
+        OsString::from_vec(b"/stale/bin:/usr/bin:/weird\xff/bin".to_vec()),
+      ),
+    ]);
+
+    let mut target = Env::default();
+    target.add_path("/tool/bin");
+    target.vars.insert("FOO".to_owned(), "bar".to_owned());
+
+    let result = process_env(vars, &target);
+    assert_eq!(result[OsStr::new("INVALID")], invalid);
+    assert_eq!(result[OsStr::new("FOO")], OsStr::new("bar"));
+    assert!(!result.contains_key(OsStr::new("STALE")));
+    assert!(!result.contains_key(OsStr::new(QWER_CURRENT)));
+    assert_eq!(
+      result[OsStr::new("PATH")],
+      OsString::from_vec(b"/tool/bin:/usr/bin:/weird\xff/bin".to_vec())
+    );
   }
 
-  let mut versions = Versions::new();
-  for mut versions_file in versions_files.into_iter().rev() {
-    versions.extend(versions_file.drain());
+  #[test]
+  fn override_vars() {
+    let plugins = ["nodejs".to_owned(), "go-sdk".to_owned(), "ruby".to_owned()];
+    let entries = override_entries(&plugins, |var| match var {
+      "ASDF_NODEJS_VERSION" => Some("18.0.0 system".to_owned()),
+      "ASDF_GO_SDK_VERSION" => Some("1.19".to_owned()),
+      "ASDF_RUBY_VERSION" => Some(" ".to_owned()),
+      _ => None,
+    });
+
+    assert_eq!(
+      entries,
+      vec![
+        ToolEntry::from_override("nodejs", vec![Version::parse("18.0.0"), Version::System]),
+        ToolEntry::from_override("go-sdk", vec![Version::parse("1.19")]),
+      ]
+    );
+    assert_eq!(entries[1].location(), "ASDF_GO_SDK_VERSION");
   }
 
-  Ok(Some(versions))
+  #[test]
+  fn untrusted_files_dont_hide_trusted_versions() {
+    let workdir = tempfile::tempdir().expect("failed to create temp dir");
+    let subdir = workdir.as_ref().join("sub");
+    fs::create_dir_all(&subdir).expect("failed to create dirs");
+    fs::write(workdir.as_ref().join("v"), "foo 1\nbar 1").expect("failed to write versions");
+    fs::write(subdir.join("v"), "foo 2").expect("failed to write versions");
+
+    let untrusted = subdir.join("v");
+    let entries = trusted_entries(&subdir, "v", &WalkOptions::default(), |path| {
+      path != untrusted
+    })
+    .expect("failed to find entries");
+
+    let foo = entries
+      .iter()
+      .find(|entry| entry.plugin == "foo")
+      .expect("foo is missing");
+    assert_eq!(foo.versions, vec![Version::parse("1")]);
+    assert_eq!(foo.source, workdir.as_ref().join("v"));
+  }
 }
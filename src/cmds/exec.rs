@@ -0,0 +1,106 @@
+use std::{
+  ffi::OsStr,
+  os::unix::{fs::PermissionsExt, process::CommandExt},
+  path::{Path, PathBuf},
+  process::Command,
+};
+
+use anyhow::{bail, Result};
+use log::trace;
+
+use crate::cmds::{
+  env::{get_process_env, get_pure_process_env, mark_used, resolve_target_env},
+  util::resolve_dir,
+};
+
+pub fn exec(cd: Option<String>, pure: bool, clear_env: bool, command: Vec<String>) -> Result<()> {
+  // Clap makes sure there is at least one argument
+  let (program, args) = command.split_first().unwrap();
+
+  let dir = resolve_dir(cd)?;
+  let target = resolve_target_env(&dir)?;
+  let target_env = target.combined();
+  mark_used(&target);
+
+  let vars = if pure {
+    get_pure_process_env(&target_env, clear_env)
+  } else {
+    get_process_env(&target_env)
+  };
+
+  let path = vars
+    .get(OsStr::new("PATH"))
+    .map(|path| path.as_os_str())
+    .unwrap_or_default();
+  let resolved = resolve_command(program, &dir, path)?;
+
+  trace!("Running {resolved:?} with args {args:?} in {dir:?}");
+
+  // `exec` only returns if the process could not be replaced
+  let err = Command::new(&resolved)
+    .args(args)
+    .current_dir(&dir)
+    .env_clear()
+    .envs(&vars)
+    .exec();
+
+  bail!("Failed to run `{program}`: {err}")
+}
+
+/// Find the executable for `program`. Anything containing a slash is a path and
+/// taken relative to `dir`, everything else is looked up in `path`, which starts
+/// with the bin paths of the tools in use.
+fn resolve_command(program: &str, dir: &Path, path: &OsStr) -> Result<PathBuf> {
+  if program.contains('/') {
+    return Ok(dir.join(program));
+  }
+
+  let found = std::env::split_paths(path)
+    .filter(|entry| !entry.as_os_str().is_empty())
+    .map(|entry| dir.join(entry).join(program))
+    .find(|candidate| is_executable(candidate));
+
+  match found {
+    Some(found) => Ok(found),
+    None => bail!("Command `{program}` not found in the tools in use or on PATH"),
+  }
+}
+
+fn is_executable(path: &Path) -> bool {
+  path
+    .metadata()
+    .map(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+    .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+  use std::{ffi::OsString, fs};
+
+  use super::*;
+
+  #[test]
+  fn resolve_command_order() {
+    let dir = tempfile::tempdir().expect("failed to create temp dir");
+    let (tool_bin, system_bin) = (dir.path().join("tool"), dir.path().join("system"));
+    for bin in [&tool_bin, &system_bin] {
+      fs::create_dir(bin).unwrap();
+      fs::write(bin.join("node"), "").unwrap();
+      fs::set_permissions(bin.join("node"), fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    // Not executable, so it has to be skipped
+    fs::write(tool_bin.join("npm"), "").unwrap();
+
+    let path = OsString::from(format!("{}:{}", tool_bin.display(), system_bin.display()));
+    assert_eq!(
+      resolve_command("node", dir.path(), &path).unwrap(),
+      tool_bin.join("node")
+    );
+    assert!(resolve_command("npm", dir.path(), &path).is_err());
+    assert_eq!(
+      resolve_command("./run.sh", dir.path(), &path).unwrap(),
+      dir.path().join("./run.sh")
+    );
+  }
+}
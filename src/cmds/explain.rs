@@ -0,0 +1,147 @@
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use console::style;
+
+use crate::{
+  cmds::{
+    env::{resolve_target_env, EnvLayer, EnvOrigin},
+    util::resolve_dir,
+  },
+  versions::Version,
+};
+
+pub fn explain(dir: Option<String>) -> Result<()> {
+  let dir = resolve_dir(dir)?;
+  let target = resolve_target_env(&dir)?;
+
+  if target.entries.is_empty() && target.layers.is_empty() {
+    println!("No env for {}", dir.to_string_lossy());
+    return Ok(());
+  }
+
+  if !target.tools.is_empty() {
+    println!("{}", style("Tools").bold());
+  }
+
+  for (tool, version) in &target.tools {
+    match version {
+      Some(version) => println!("  {} {}", tool.plugin, style(version.raw()).cyan()),
+      None => println!(
+        "  {} {}",
+        tool.plugin,
+        style(format!(
+          "not installed ({})",
+          version_options(&tool.versions)
+        ))
+        .red()
+      ),
+    }
+
+    println!("    {}", style(tool.location()).dim());
+
+    let overridden = target
+      .entries
+      .iter()
+      .filter(|entry| entry.plugin == tool.plugin && *entry != tool);
+
+    for entry in overridden {
+      println!(
+        "    {}",
+        style(format!(
+          "overrides {} from {}",
+          version_options(&entry.versions),
+          entry.location()
+        ))
+        .dim()
+      );
+    }
+  }
+
+  // Every var with all the layers that set it, the first one wins
+  let mut vars = BTreeMap::<&str, Vec<(&EnvLayer, &str)>>::new();
+  for layer in &target.layers {
+    for (key, val) in &layer.env.vars {
+      vars.entry(key).or_default().push((layer, val));
+    }
+  }
+
+  if !vars.is_empty() {
+    println!("\n{}", style("Vars").bold());
+  }
+
+  for (key, sources) in vars {
+    let (winner, val) = sources[0];
+    println!("  {key}={val}");
+    println!("    {}", style(describe(winner, Some(key))).dim());
+
+    for (layer, val) in &sources[1..] {
+      println!(
+        "    {}",
+        style(format!(
+          "overrides `{val}` from {}",
+          describe(layer, Some(key))
+        ))
+        .dim()
+      );
+    }
+  }
+
+  let path = target.combined().path;
+  if !path.is_empty() {
+    println!("\n{}", style("PATH").bold());
+  }
+
+  // The combined path keeps the first position of every entry
+  for entry in path {
+    println!("  {entry}");
+
+    let mut sources = target
+      .layers
+      .iter()
+      .filter(|layer| layer.env.path.contains(&entry));
+
+    if let Some(first) = sources.next() {
+      println!("    {}", style(describe(first, None)).dim());
+    }
+
+    for layer in sources {
+      println!(
+        "    {}",
+        style(format!("also added by {}", describe(layer, None))).dim()
+      );
+    }
+  }
+
+  Ok(())
+}
+
+/// Describe where a layer came from, including the line of `key` if it is known.
+fn describe(layer: &EnvLayer, key: Option<&str>) -> String {
+  match &layer.origin {
+    EnvOrigin::Project(path) | EnvOrigin::Dotenv(path) => {
+      match key.and_then(|key| layer.lines.get(key)) {
+        Some(line) => format!("{}:{line}", path.to_string_lossy()),
+        None => path.to_string_lossy().to_string(),
+      }
+    }
+    EnvOrigin::Tool {
+      entry,
+      version,
+      script,
+    } => format!(
+      "{} {} {script} ({})",
+      entry.plugin,
+      version.raw(),
+      entry.location()
+    ),
+  }
+}
+
+fn version_options(versions: &[Version]) -> String {
+  versions
+    .iter()
+    .map(|version| version.raw())
+    .collect::<Vec<_>>()
+    .join(" ")
+}
@@ -1,31 +1,142 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, sync::mpsc, thread};
 
 use anyhow::{bail, Result};
 use console::style;
-use log::{info, trace};
+use indicatif::ProgressBar;
+use log::{info, trace, warn};
+use threadpool::ThreadPool;
 
 use crate::{
-  dirs::{get_plugin_scripts, TOOL_VERSIONS},
+  cache::CacheLimits,
+  cmds::{self, env::get_process_env},
+  dirs::{get_plugin_scripts, get_user_hooks, get_walk_options, TOOL_VERSIONS},
+  env::Env,
+  hooks::{HookKind, UserHooks},
+  lock::LockEntry,
   pretty,
   process::auto_bar,
+  scripts::PluginScripts,
   versions::{Version, Versions},
 };
 
-pub fn install_all(concurrency: Option<usize>, keep_download: bool) -> Result<()> {
+pub fn install_all(
+  concurrency: Option<usize>,
+  keep_download: bool,
+  frozen: bool,
+  jobs: Option<usize>,
+) -> Result<()> {
   let to_install = gather_versions()?;
   trace!("Installing versions:\n{to_install:#?}");
+  record_current_projects()?;
+
+  let lockfile = if frozen {
+    Some(cmds::lock::verify_frozen(&to_install)?)
+  } else {
+    None
+  };
+
+  let mut to_install = to_install
+    .into_iter()
+    .map(|(plugin, version)| {
+      let locked = lockfile
+        .as_ref()
+        .map(|lockfile| lockfile.tools[&plugin].clone());
+      (plugin, version, locked)
+    })
+    .collect::<Vec<_>>();
+  to_install.sort_by(|a, b| a.0.cmp(&b.0));
+
+  let total = to_install.len();
+  let jobs = jobs
+    .or_else(|| thread::available_parallelism().ok().map(|num| num.get()))
+    .unwrap_or(1)
+    .max(1);
+
+  trace!("Installing {total} tools with {jobs} jobs");
+
+  // Tools don't depend on each other, so they can all be installed at the same
+  // time. Every install shows its own progress bar.
+  let pool = ThreadPool::new(jobs);
+  let (sender, receiver) = mpsc::channel();
+  for (plugin, version, locked) in to_install {
+    let sender = sender.clone();
+    pool.execute(move || {
+      let result = install_tool(
+        &plugin,
+        &version,
+        locked.as_ref(),
+        concurrency,
+        keep_download,
+      );
+      let _ = sender.send((plugin, version, result));
+    });
+  }
 
-  let mut to_install = to_install.iter().collect::<Vec<(&String, &Version)>>();
-  to_install.sort_by_key(|(version, _)| version.to_owned());
+  drop(sender);
+  let mut results = receiver.into_iter().collect::<Vec<_>>();
+  results.sort_by(|a, b| a.0.cmp(&b.0));
 
-  for (plugin, version) in to_install {
-    let scripts = get_plugin_scripts(plugin)?;
-    if scripts.version_installed(version) {
-      info!("{} {} already installed", &plugin, version.raw());
-      continue;
+  // Pruning once all installs are done keeps parallel installs from pruning the
+  // same downloads at the same time
+  cmds::cache::auto_prune(&CacheLimits::from_env()?);
+
+  if total > 1 {
+    println!();
+    for (plugin, version, result) in &results {
+      match result {
+        Ok(()) => println!(
+          "{} {}",
+          style("✔").green(),
+          pretty::plugin_version(plugin, &version.raw())
+        ),
+        Err(err) => println!(
+          "{} {}: {err}",
+          style("✘").red(),
+          pretty::plugin_version(plugin, &version.raw())
+        ),
+      }
     }
+  }
 
-    install(plugin, &version.raw(), concurrency, keep_download)?;
+  // Installs that panicked never report back
+  let failed = total
+    - results
+      .iter()
+      .filter(|(_, _, result)| result.is_ok())
+      .count();
+
+  if failed == 0 {
+    return Ok(());
+  }
+
+  // A single tool has no summary, so report its error directly
+  if let (1, Some((_, _, Err(err)))) = (total, results.pop()) {
+    return Err(err);
+  }
+
+  bail!("Failed to install {failed} of {total} tools")
+}
+
+pub fn install_tool(
+  plugin: &str,
+  version: &Version,
+  locked: Option<&LockEntry>,
+  concurrency: Option<usize>,
+  keep_download: bool,
+) -> Result<()> {
+  let version = match locked {
+    Some(locked) => Version::parse(&locked.version),
+    None => version.clone(),
+  };
+
+  let scripts = get_plugin_scripts(plugin)?;
+  if !scripts.version_installed(&version) {
+    return install(plugin, &version.raw(), locked, concurrency, keep_download);
+  }
+
+  info!("{} {} already installed", &plugin, version.raw());
+  if let Some(locked) = locked {
+    cmds::lock::verify_checksum(&scripts, plugin, locked)?;
   }
 
   Ok(())
@@ -36,15 +147,22 @@ pub fn install_one(name: String, concurrency: Option<usize>, keep_download: bool
   if !versions.contains_key(&name) {
     bail!("Tool `{name}` is not defined in any version files");
   }
+  record_current_projects()?;
 
   let to_install = &versions[&name];
   trace!("Installing version: {name} {to_install:?}");
 
-  install(&name, &to_install.raw(), concurrency, keep_download)
+  let result = install(&name, &to_install.raw(), None, concurrency, keep_download);
+  cmds::cache::auto_prune(&CacheLimits::from_env()?);
+  result
 }
 
-fn gather_versions() -> Result<HashMap<String, Version>> {
-  let version_files = Versions::find_all(std::env::current_dir()?, TOOL_VERSIONS)?;
+pub fn gather_versions() -> Result<HashMap<String, Version>> {
+  let version_files = Versions::find_all(
+    std::env::current_dir()?,
+    TOOL_VERSIONS,
+    &get_walk_options()?,
+  )?;
   let mut result = HashMap::new();
 
   for versions in version_files {
@@ -65,18 +183,33 @@ fn gather_versions() -> Result<HashMap<String, Version>> {
   Ok(result)
 }
 
+/// The versions files an install resolved belong to projects that use qwer.
+fn record_current_projects() -> Result<()> {
+  let files = Versions::find_all_files(
+    std::env::current_dir()?,
+    TOOL_VERSIONS,
+    &get_walk_options()?,
+  )?;
+
+  cmds::projects::record_projects(&files);
+  Ok(())
+}
+
 pub fn install_one_version(
   name: String,
   version: String,
   concurrency: Option<usize>,
   keep_download: bool,
 ) -> Result<()> {
-  install(&name, &version, concurrency, keep_download)
+  let result = install(&name, &version, None, concurrency, keep_download);
+  cmds::cache::auto_prune(&CacheLimits::from_env()?);
+  result
 }
 
 fn install(
   name: &str,
   version: &str,
+  locked: Option<&LockEntry>,
   concurrency: Option<usize>,
   keep_download: bool,
 ) -> Result<()> {
@@ -99,14 +232,93 @@ fn install(
     bail!("Can't install system version");
   }
 
+  // Downloads are managed by the cache as soon as it has limits
+  let limits = CacheLimits::from_env()?;
+  let keep_download = keep_download || limits.enabled();
+
+  let hooks = get_user_hooks(name)?;
+  let name_version = pretty::plugin_version(name, resolved.version_str());
+  let bar = auto_bar();
+  let result = run_install(
+    name,
+    &scripts,
+    &hooks,
+    &resolved,
+    concurrency,
+    keep_download,
+    &bar,
+  );
+
+  // The checksum is verified before the post-install hook can change the version
+  let result = result.and_then(|()| match locked {
+    Some(locked) => cmds::lock::verify_checksum(&scripts, name, locked),
+    None => Ok(()),
+  });
+
+  if let Err(err) = result {
+    bar.abandon_with_message(format!(
+      "{} {name_version}",
+      style("Failed to install").red()
+    ));
+    return Err(err);
+  }
+
+  // The install itself worked, so a failing hook must not undo it
+  match run_hook(
+    name,
+    &scripts,
+    &hooks,
+    HookKind::PostInstall,
+    &resolved,
+    &bar,
+  ) {
+    Ok(()) => bar.finish_with_message(format!("Installed {name_version}")),
+    Err(err) => {
+      bar.finish_with_message(format!(
+        "Installed {name_version}, {}",
+        style("but its post-install hook failed").yellow()
+      ));
+      warn!("{err}");
+    }
+  }
+
+  Ok(())
+}
+
+fn run_install(
+  name: &str,
+  scripts: &PluginScripts,
+  hooks: &UserHooks,
+  version: &Version,
+  concurrency: Option<usize>,
+  keep_download: bool,
+  bar: &ProgressBar,
+) -> Result<()> {
+  scripts.reset_log(version)?;
+  run_hook(name, scripts, hooks, HookKind::PreInstall, version, bar)?;
+
+  // Keeps the download from being pruned by other installs until we're done
+  scripts.lock_download(version)?;
+  let result = download_and_install(scripts, version, concurrency, bar);
+  scripts.unlock_download(version, keep_download)?;
+  result
+}
+
+fn download_and_install(
+  scripts: &PluginScripts,
+  version: &Version,
+  concurrency: Option<usize>,
+  bar: &ProgressBar,
+) -> Result<()> {
   if scripts.has_download() {
-    scripts.download(&resolved)?;
+    scripts.download(version, bar)?;
   }
 
-  scripts.install(&resolved, concurrency)?;
+  scripts.install(version, concurrency, bar)?;
+  scripts.rm_exec_env_cache(version)?;
 
-  if !keep_download {
-    scripts.rm_version_download(&resolved)?;
+  if !scripts.has_download() {
+    scripts.mark_download_complete(version)?;
   }
 
   Ok(())
@@ -122,12 +334,30 @@ pub fn uninstall(name: String, version: String) -> Result<()> {
     );
   }
 
+  let hooks = get_user_hooks(&name)?;
   let bar = auto_bar();
   bar.set_message(format!(
     "Uninstalling {}...",
     pretty::plugin_version(&name, version.version_str()),
   ));
 
+  // The pre-uninstall hook can keep a version around by failing
+  if let Err(err) = run_hook(
+    &name,
+    &scripts,
+    &hooks,
+    HookKind::PreUninstall,
+    &version,
+    &bar,
+  ) {
+    bar.abandon_with_message(format!(
+      "{} {}",
+      style("Failed to uninstall").red(),
+      pretty::plugin_version(&name, version.version_str()),
+    ));
+    return Err(err);
+  }
+
   if scripts.has_uninstall() {
     scripts.uninstall(
       (
@@ -145,10 +375,70 @@ pub fn uninstall(name: String, version: String) -> Result<()> {
 
   // Just in case this wasn't cleaned earlier
   scripts.rm_version_download(&version)?;
-  bar.finish_with_message(format!(
-    "Uninstalled {}",
-    pretty::plugin_version(&name, version.version_str()),
-  ));
+  scripts.rm_exec_env_cache(&version)?;
+
+  match run_hook(
+    &name,
+    &scripts,
+    &hooks,
+    HookKind::PostUninstall,
+    &version,
+    &bar,
+  ) {
+    Ok(()) => bar.finish_with_message(format!(
+      "Uninstalled {}",
+      pretty::plugin_version(&name, version.version_str()),
+    )),
+    Err(err) => {
+      bar.finish_with_message(format!(
+        "Uninstalled {}, {}",
+        pretty::plugin_version(&name, version.version_str()),
+        style("but its post-uninstall hook failed").yellow()
+      ));
+      warn!("{err}");
+    }
+  }
+
+  Ok(())
+}
+
+/// Run a user hook of `name` for `version`. Hooks that run while the version is
+/// installed get its env, the same way `qwer exec` would activate it.
+fn run_hook(
+  name: &str,
+  scripts: &PluginScripts,
+  hooks: &UserHooks,
+  kind: HookKind,
+  version: &Version,
+  bar: &ProgressBar,
+) -> Result<()> {
+  if !hooks.has_hook(kind) {
+    return Ok(());
+  }
+
+  let target_env = match kind {
+    HookKind::PostInstall | HookKind::PreUninstall => scripts.get_version_env(version)?.combined(),
+    HookKind::PreInstall | HookKind::PostUninstall => Env::default(),
+  };
+
+  // Hooks get the same env as `qwer exec` would, so whatever the shell hook
+  // applied for other versions doesn't leak into them
+  let env = get_process_env(&target_env);
+
+  hooks.run(
+    (
+      bar,
+      &format!(
+        "Running {kind} hook for {}...",
+        pretty::plugin_version(name, version.version_str())
+      ),
+    ),
+    kind,
+    version,
+    &scripts.version_install_path(version),
+    &env,
+    &scripts.log_path(version),
+  )?;
 
   Ok(())
 }
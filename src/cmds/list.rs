@@ -1,8 +1,13 @@
 use std::fs::{self, DirEntry};
 
 use anyhow::{bail, Result};
+use log::warn;
 
-use crate::dirs::{get_dir, get_plugin_scripts, INSTALLS_DIR};
+use crate::{
+  dirs::{get_dir, get_plugin_scripts, INSTALLS_DIR},
+  pretty,
+  scripts::install_complete,
+};
 
 pub fn all_installed() -> Result<()> {
   let install_dir = get_dir(INSTALLS_DIR)?;
@@ -53,6 +58,19 @@ fn get_installed_versions(name: &str, filter: Option<String>) -> Result<Vec<Stri
     .collect::<Result<Vec<DirEntry>, std::io::Error>>()?
     .iter()
     .map(|entry| entry.file_name().to_string_lossy().to_string())
+    // Skip install markers
+    .filter(|version| !version.starts_with('.'))
+    .filter(|version| {
+      if install_complete(&install_dir, version) {
+        return true;
+      }
+
+      warn!(
+        "{} was not installed completely, run `qwer install {name} {version}` to try again",
+        pretty::plugin_version(name, version)
+      );
+      false
+    })
     .collect::<Vec<_>>();
 
   let filtered = if let Some(filter) = filter {
@@ -0,0 +1,319 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use anyhow::{bail, Result};
+use log::{info, trace};
+
+use crate::{
+  cmds::install::gather_versions,
+  dirs::{get_dir, get_plugin_scripts, get_walk_options, PLUGINS_DIR, TOOL_VERSIONS},
+  git::GitRepo,
+  lock::{LockEntry, Lockfile},
+  pretty,
+  scripts::PluginScripts,
+  versions::{Version, Versions},
+};
+
+pub fn lock(checksums: bool) -> Result<()> {
+  let lockfile_path = find_lockfile_path()?;
+  let versions = gather_versions()?;
+
+  let mut to_lock = versions.iter().collect::<Vec<(&String, &Version)>>();
+  to_lock.sort_by_key(|(plugin, _)| plugin.to_owned());
+
+  let mut lockfile = Lockfile::new();
+  for (plugin, version) in to_lock {
+    let mut entry = resolve_lock_entry(plugin, version)?;
+
+    if checksums {
+      let scripts = get_plugin_scripts(plugin)?;
+      let resolved = Version::parse(&entry.version);
+      if !scripts.version_installed(&resolved) {
+        bail!(
+          "{} must be installed to record its checksum",
+          pretty::plugin_version(plugin, &entry.version)
+        );
+      }
+
+      entry.checksum = Some(scripts.install_checksum(&resolved)?);
+    }
+
+    lockfile.tools.insert(plugin.clone(), entry);
+  }
+
+  lockfile.save(&lockfile_path)?;
+  info!("Wrote lockfile to {}", lockfile_path.to_string_lossy());
+
+  Ok(())
+}
+
+/// Load the lockfile for the current directory and make sure it matches what the
+/// versions files resolve to right now. All mismatches are collected and reported
+/// together, so fixing a lockfile doesn't take more than one run.
+pub fn verify_frozen(versions: &HashMap<String, Version>) -> Result<Lockfile> {
+  let lockfile_path = find_lockfile_path()?;
+  if !lockfile_path.is_file() {
+    bail!(
+      "No lockfile found at {}, run `qwer lock` first",
+      lockfile_path.to_string_lossy()
+    );
+  }
+
+  let lockfile = Lockfile::load(&lockfile_path)?;
+  let errors = frozen_errors(&lockfile, versions, resolve_lock_entry)?;
+  if !errors.is_empty() {
+    bail!("Lockfile is out of date:\n  {}", errors.join("\n  "));
+  }
+
+  Ok(lockfile)
+}
+
+/// Compare `lockfile` to what `versions` resolve to with `resolve`, returning
+/// every mismatch.
+fn frozen_errors(
+  lockfile: &Lockfile,
+  versions: &HashMap<String, Version>,
+  resolve: impl Fn(&str, &Version) -> Result<LockEntry>,
+) -> Result<Vec<String>> {
+  let mut errors = Vec::new();
+
+  let mut plugins = versions.keys().collect::<Vec<_>>();
+  plugins.sort();
+
+  for plugin in plugins {
+    let locked = match lockfile.tools.get(plugin) {
+      Some(locked) => locked,
+      None => {
+        errors.push(format!("`{plugin}` is missing from the lockfile"));
+        continue;
+      }
+    };
+
+    let current = resolve(plugin, &versions[plugin])?;
+    trace!("Comparing locked `{locked:?}` to current `{current:?}`");
+
+    if current.version != locked.version {
+      errors.push(format!(
+        "`{plugin}` resolved to `{}`, but `{}` is locked",
+        current.version, locked.version
+      ));
+    }
+
+    if current.plugin_url != locked.plugin_url {
+      errors.push(format!(
+        "plugin `{plugin}` is installed from `{}`, but `{}` is locked",
+        current.plugin_url, locked.plugin_url
+      ));
+    }
+
+    if current.plugin_ref != locked.plugin_ref {
+      errors.push(format!(
+        "plugin `{plugin}` is at ref `{}`, but `{}` is locked",
+        current.plugin_ref, locked.plugin_ref
+      ));
+    }
+  }
+
+  for plugin in lockfile.tools.keys() {
+    if !versions.contains_key(plugin) {
+      errors.push(format!(
+        "`{plugin}` is locked but not defined in any version files"
+      ));
+    }
+  }
+
+  Ok(errors)
+}
+
+/// Check an installed version against the checksum recorded in its lock entry.
+/// Only what the install script built is compared, not what hooks added later.
+pub fn verify_checksum(scripts: &PluginScripts, plugin: &str, entry: &LockEntry) -> Result<()> {
+  let expected = match &entry.checksum {
+    Some(expected) => expected,
+    None => return Ok(()),
+  };
+
+  let actual = scripts.install_checksum(&Version::parse(&entry.version))?;
+  if &actual != expected {
+    bail!(
+      "Checksum mismatch for {}: expected `{expected}`, got `{actual}`",
+      pretty::plugin_version(plugin, &entry.version)
+    );
+  }
+
+  Ok(())
+}
+
+fn resolve_lock_entry(plugin: &str, version: &Version) -> Result<LockEntry> {
+  let scripts = get_plugin_scripts(plugin)?;
+  let resolved = match scripts.resolve(&version.raw())? {
+    Some(resolved) => resolved,
+    None => bail!(
+      "Failed to resolve version {} for plugin {}",
+      pretty::version(&version.raw()),
+      pretty::plugin(plugin)
+    ),
+  };
+
+  let repo = GitRepo::new(get_dir(PLUGINS_DIR)?.join(plugin))?;
+
+  Ok(LockEntry {
+    version: resolved.raw(),
+    plugin_url: repo.get_remote_url()?.trim().to_owned(),
+    plugin_ref: repo.get_head_commit()?.trim().to_owned(),
+    checksum: None,
+  })
+}
+
+/// The lockfile lives next to the closest versions file.
+fn find_lockfile_path() -> Result<PathBuf> {
+  let versions_files = Versions::find_all_files(
+    std::env::current_dir()?,
+    TOOL_VERSIONS,
+    &get_walk_options()?,
+  )?;
+
+  match versions_files.first() {
+    Some(versions_file) => Ok(Lockfile::path_for(versions_file)),
+    None => bail!("No versions file found in the current or any parent directories"),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::{collections::HashMap, ffi::OsString, fs, os::unix::fs::PermissionsExt};
+
+  use indicatif::ProgressBar;
+
+  use super::*;
+  use crate::{
+    hooks::{HookKind, UserHooks},
+    lock::checksum_dir,
+  };
+
+  fn entry(version: &str) -> LockEntry {
+    LockEntry {
+      version: version.to_owned(),
+      plugin_url: "https://example.com/plugin".to_owned(),
+      plugin_ref: "abc123".to_owned(),
+      checksum: None,
+    }
+  }
+
+  #[test]
+  fn frozen_mismatches() {
+    let mut lockfile = Lockfile::new();
+    lockfile.tools.insert("nodejs".to_owned(), entry("18.0.0"));
+    lockfile.tools.insert("python".to_owned(), entry("3.11.0"));
+    lockfile.tools.insert("ruby".to_owned(), entry("3.1.0"));
+
+    let versions = HashMap::from([
+      ("nodejs".to_owned(), Version::parse("18.0.0")),
+      ("python".to_owned(), Version::parse("latest")),
+      ("rust".to_owned(), Version::parse("1.70.0")),
+    ]);
+
+    let resolve = |plugin: &str, version: &Version| {
+      Ok(match plugin {
+        "python" => entry("3.12.0"),
+        _ => entry(&version.raw()),
+      })
+    };
+
+    assert_eq!(
+      frozen_errors(&lockfile, &versions, resolve).expect("failed to compare"),
+      vec![
+        "`python` resolved to `3.12.0`, but `3.11.0` is locked",
+        "`rust` is missing from the lockfile",
+        "`ruby` is locked but not defined in any version files",
+      ]
+    );
+  }
+
+  #[test]
+  fn frozen_plugin_changes() {
+    let mut lockfile = Lockfile::new();
+    lockfile.tools.insert("nodejs".to_owned(), entry("18.0.0"));
+    let versions = HashMap::from([("nodejs".to_owned(), Version::parse("18.0.0"))]);
+
+    let unchanged = frozen_errors(&lockfile, &versions, |_, version| Ok(entry(&version.raw())));
+    assert!(unchanged.expect("failed to compare").is_empty());
+
+    let moved = frozen_errors(&lockfile, &versions, |_, version| {
+      Ok(LockEntry {
+        plugin_url: "https://example.com/fork".to_owned(),
+        plugin_ref: "def456".to_owned(),
+        ..entry(&version.raw())
+      })
+    });
+    assert_eq!(
+      moved.expect("failed to compare"),
+      vec![
+        "plugin `nodejs` is installed from `https://example.com/fork`, but `https://example.com/plugin` is locked",
+        "plugin `nodejs` is at ref `def456`, but `abc123` is locked",
+      ]
+    );
+  }
+
+  #[test]
+  fn checksums_ignore_hooks() {
+    let workdir = tempfile::tempdir().expect("failed to create temp dir");
+    let root = workdir.as_ref();
+    let write_script = |path: std::path::PathBuf, script: &str| {
+      fs::create_dir_all(path.parent().unwrap()).expect("failed to create dirs");
+      fs::write(&path, format!("#!/bin/sh\n{script}\n")).expect("failed to write script");
+      fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).expect("failed to chmod");
+    };
+
+    write_script(
+      root.join("plugins/foo/bin/install"),
+      "echo tool > \"$ASDF_INSTALL_PATH/tool\"",
+    );
+    write_script(
+      root.join("hooks/post-install"),
+      "echo package > \"$ASDF_INSTALL_PATH/package\"",
+    );
+
+    let scripts = PluginScripts::new(
+      "foo",
+      root.join("plugins"),
+      root.join("installs"),
+      root.join("downloads"),
+      root.join("cache"),
+      root.join("logs"),
+      &[],
+    )
+    .expect("failed to create scripts");
+    let version = Version::parse("1.0");
+    let bar = ProgressBar::hidden();
+    scripts
+      .install(&version, None, &bar)
+      .expect("failed to install");
+
+    let mut locked = entry("1.0");
+    locked.checksum = Some(scripts.install_checksum(&version).unwrap());
+
+    // The hook installs a package into the version, like with default packages
+    let install_path = scripts.version_install_path(&version);
+    let env = HashMap::from([(
+      OsString::from("PATH"),
+      std::env::var_os("PATH").unwrap_or_default(),
+    )]);
+    UserHooks::new(root.join("hooks"))
+      .run(
+        (&bar, ""),
+        HookKind::PostInstall,
+        &version,
+        &install_path,
+        &env,
+        &root.join("hook.log"),
+      )
+      .expect("hook failed");
+    assert!(install_path.join("package").is_file());
+    assert_ne!(locked.checksum, Some(checksum_dir(&install_path).unwrap()));
+
+    verify_checksum(&scripts, "foo", &locked).expect("checksum changed");
+
+    locked.checksum = Some("0".repeat(64));
+    assert!(verify_checksum(&scripts, "foo", &locked).is_err());
+  }
+}
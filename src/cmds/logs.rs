@@ -0,0 +1,33 @@
+use std::fs;
+
+use anyhow::{bail, Result};
+
+use crate::{dirs::get_plugin_scripts, pretty, versions::Version};
+
+pub fn logs(name: String, version: Option<String>) -> Result<()> {
+  let scripts = get_plugin_scripts(&name)?;
+
+  let log_path = match version {
+    Some(version) => {
+      let version = Version::parse(&version);
+      let log_path = scripts.log_path(&version);
+      if !log_path.is_file() {
+        bail!(
+          "No logs for {}",
+          pretty::plugin_version(&name, version.version_str())
+        );
+      }
+
+      log_path
+    }
+    // Without a version, show whatever was installed last
+    None => match scripts.logs()?.into_iter().next() {
+      Some(log_path) => log_path,
+      None => bail!("No logs for {name}"),
+    },
+  };
+
+  print!("{}", fs::read_to_string(log_path)?);
+
+  Ok(())
+}
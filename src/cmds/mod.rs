@@ -1,9 +1,21 @@
+pub mod cache;
+pub mod check;
+pub mod du;
 pub mod env;
+pub mod exec;
+pub mod explain;
 pub mod ext;
 pub mod help;
 pub mod install;
 pub mod list;
+pub mod lock;
+pub mod logs;
+pub mod outdated;
 pub mod plugin;
+pub mod projects;
+pub mod prune;
+pub mod trust;
+pub mod upgrade;
 pub mod util;
 pub mod uuse;
 pub mod version;
@@ -0,0 +1,184 @@
+use std::{sync::mpsc, thread};
+
+use anyhow::{bail, Result};
+use console::style;
+use log::warn;
+use serde::Serialize;
+use tabled::{object::Segment, Alignment, Modify, Table, Tabled};
+use threadpool::ThreadPool;
+
+use crate::{
+  dirs::{get_plugin_scripts, get_walk_options, TOOL_VERSIONS},
+  versions::{bump_between, Bump, ToolEntry, Version, Versions},
+};
+
+#[derive(Debug, Serialize)]
+struct OutdatedEntry {
+  name: String,
+  pinned: String,
+  installed: Option<String>,
+  latest: Option<String>,
+  bump: Option<Bump>,
+  source: String,
+
+  #[serde(skip_serializing_if = "Option::is_none")]
+  error: Option<String>,
+}
+
+#[derive(Tabled)]
+struct OutdatedItem {
+  name: String,
+  pinned: String,
+  installed: String,
+  latest: String,
+  source: String,
+}
+
+pub fn outdated(global: bool, json: bool, fail_on: Option<Bump>) -> Result<()> {
+  let toolset = if global {
+    let global_files = get_walk_options()?
+      .global_files
+      .into_iter()
+      .filter(|path| path.is_file())
+      .collect::<Vec<_>>();
+
+    Versions::toolset_from_files(&global_files)?
+  } else {
+    Versions::find_toolset(
+      std::env::current_dir()?,
+      TOOL_VERSIONS,
+      &get_walk_options()?,
+    )?
+  };
+
+  let entries = lookup_all(toolset);
+
+  for entry in &entries {
+    if let Some(err) = &entry.error {
+      warn!("Failed to look up versions for {}: {err}", entry.name);
+    }
+  }
+
+  if json {
+    println!("{}", serde_json::to_string_pretty(&entries)?);
+  } else if entries.is_empty() {
+    println!("No tools in use");
+  } else {
+    print_table(&entries);
+  }
+
+  let fail_on = match fail_on {
+    Some(fail_on) => fail_on,
+    None => return Ok(()),
+  };
+
+  // Without a lookup there's no way to tell whether the tool is up to date
+  let failed = entries.iter().filter(|entry| entry.error.is_some()).count();
+  if failed > 0 {
+    bail!("Failed to look up versions for {failed} tools");
+  }
+
+  let behind = entries
+    .iter()
+    .filter(|entry| entry.bump.is_some_and(|bump| bump >= fail_on))
+    .count();
+
+  if behind > 0 {
+    bail!("{behind} tools are at least a {fail_on} version behind");
+  }
+
+  Ok(())
+}
+
+/// Look up the installed and latest versions of every tool. Running the plugin
+/// scripts is the slow part, so all tools are looked up at the same time.
+fn lookup_all(toolset: Vec<ToolEntry>) -> Vec<OutdatedEntry> {
+  let total = toolset.len();
+  let jobs = thread::available_parallelism()
+    .map(|num| num.get())
+    .unwrap_or(1);
+
+  let pool = ThreadPool::new(jobs);
+  let (sender, receiver) = mpsc::channel();
+  for (index, tool) in toolset.into_iter().enumerate() {
+    let sender = sender.clone();
+    pool.execute(move || {
+      let _ = sender.send((index, lookup(tool)));
+    });
+  }
+
+  drop(sender);
+  let mut results = receiver.into_iter().collect::<Vec<_>>();
+  results.sort_by_key(|(index, _)| *index);
+
+  if results.len() < total {
+    warn!("Failed to look up versions for some tools");
+  }
+
+  results.into_iter().map(|(_, entry)| entry).collect()
+}
+
+fn lookup(tool: ToolEntry) -> OutdatedEntry {
+  // Only the first version of an entry is the one that's pinned, the others
+  // are fallbacks
+  let pinned = tool.versions.first().unwrap();
+  let mut entry = OutdatedEntry {
+    name: tool.plugin.clone(),
+    pinned: pinned.raw(),
+    installed: None,
+    latest: None,
+    bump: None,
+    source: format!("{}:{}", tool.source.to_string_lossy(), tool.line),
+    error: None,
+  };
+
+  let result = get_plugin_scripts(&tool.plugin).and_then(|scripts| {
+    let installed = scripts.installed_versions()?.pop();
+    let latest = scripts.latest_stable()?;
+    Ok((installed, latest))
+  });
+
+  match result {
+    Ok((installed, latest)) => {
+      // Refs, paths and `system` aren't pinned to a release, so they are never behind
+      if let (Version::Remote(pinned), Some(latest)) = (pinned, &latest) {
+        entry.bump = bump_between(pinned, latest.version_str());
+      }
+
+      entry.installed = installed;
+      entry.latest = latest.map(|latest| latest.raw());
+    }
+    Err(err) => entry.error = Some(err.to_string()),
+  }
+
+  entry
+}
+
+fn print_table(entries: &[OutdatedEntry]) {
+  let items = entries.iter().map(|entry| OutdatedItem {
+    name: entry.name.clone(),
+    pinned: match entry.bump {
+      Some(_) => style(&entry.pinned).yellow().to_string(),
+      None => style(&entry.pinned).green().to_string(),
+    },
+    installed: entry.installed.clone().unwrap_or_else(|| "-".to_owned()),
+    latest: match (&entry.latest, entry.bump, &entry.error) {
+      (_, _, Some(_)) => style("lookup failed").red().to_string(),
+      (Some(latest), Some(bump), _) => format!(
+        "{} {}",
+        style(latest).cyan(),
+        style(format!("({bump})")).dim()
+      ),
+      (Some(latest), None, _) => style(latest).cyan().to_string(),
+      (None, _, _) => "-".to_owned(),
+    },
+    source: style(&entry.source).dim().to_string(),
+  });
+
+  let table = Table::new(items)
+    .with(tabled::Style::blank())
+    .with(Modify::new(Segment::all()).with(Alignment::left()))
+    .to_string();
+
+  println!("\n{table}");
+}
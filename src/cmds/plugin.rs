@@ -5,7 +5,7 @@ use console::style;
 use tabled::{object::Segment, Alignment, Modify, Table, Tabled};
 
 use crate::{
-  dirs::{get_dir, get_plugin_scripts, INSTALLS_DIR, PLUGINS_DIR},
+  dirs::{get_dir, get_plugin_scripts, EXEC_ENV_CACHE_DIR, INSTALLS_DIR, PLUGINS_DIR},
   git, plugins,
   process::auto_bar,
 };
@@ -112,6 +112,11 @@ pub fn remove(name: String) -> Result<()> {
     fs::remove_dir_all(install_dir)?;
   }
 
+  let exec_env_cache_dir = get_dir(EXEC_ENV_CACHE_DIR)?.join(&name);
+  if exec_env_cache_dir.is_dir() {
+    fs::remove_dir_all(exec_env_cache_dir)?;
+  }
+
   Ok(())
 }
 
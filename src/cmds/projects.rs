@@ -0,0 +1,134 @@
+use std::{
+  path::PathBuf,
+  time::{Duration, UNIX_EPOCH},
+};
+
+use anyhow::Result;
+use console::style;
+use log::{info, trace};
+use serde::Serialize;
+
+use crate::{
+  dirs::{get_data_dir, get_walk_options, PROJECT_REGISTRY, TOOL_VERSIONS},
+  projects::ProjectRegistry,
+  versions::{Version, Versions},
+};
+
+#[derive(Debug, Serialize)]
+struct ProjectEntry {
+  dir: String,
+  last_seen: Option<String>,
+  tools: Vec<ProjectTool>,
+
+  #[serde(skip_serializing_if = "Option::is_none")]
+  error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct ProjectTool {
+  name: String,
+  versions: Vec<String>,
+}
+
+pub fn projects(json: bool) -> Result<()> {
+  let registry_path = get_data_dir()?.join(PROJECT_REGISTRY);
+  let mut registry = ProjectRegistry::load(&registry_path)?;
+
+  let missing = registry.remove_missing();
+  if !missing.is_empty() {
+    for dir in &missing {
+      info!(
+        "Forgetting {}, it has no versions file anymore",
+        dir.to_string_lossy()
+      );
+    }
+
+    registry.save(&registry_path)?;
+  }
+
+  let entries = registry
+    .dirs()
+    .map(|dir| {
+      let mut entry = ProjectEntry {
+        dir: dir.to_string_lossy().to_string(),
+        last_seen: registry.last_seen(&dir).map(|secs| {
+          humantime::format_rfc3339_seconds(UNIX_EPOCH + Duration::from_secs(secs)).to_string()
+        }),
+        tools: vec![],
+        error: None,
+      };
+
+      match Versions::toolset_from_files(&[dir.join(TOOL_VERSIONS)]) {
+        Ok(toolset) => {
+          entry.tools = toolset
+            .into_iter()
+            .map(|tool| ProjectTool {
+              name: tool.plugin,
+              versions: tool.versions.iter().map(Version::raw).collect(),
+            })
+            .collect()
+        }
+        Err(err) => entry.error = Some(err.to_string()),
+      }
+
+      entry
+    })
+    .collect::<Vec<_>>();
+
+  if json {
+    println!("{}", serde_json::to_string_pretty(&entries)?);
+    return Ok(());
+  }
+
+  if entries.is_empty() {
+    println!("No known projects");
+    return Ok(());
+  }
+
+  for entry in entries {
+    println!("{}", style(&entry.dir).bold());
+
+    if let Some(err) = entry.error {
+      println!("  {}", style(err).red());
+    }
+
+    for tool in entry.tools {
+      println!("  {} {}", tool.name, style(tool.versions.join(" ")).cyan());
+    }
+  }
+
+  Ok(())
+}
+
+/// Remember the projects of the versions files in `files`. The global versions
+/// files aren't projects and are skipped. This runs as part of the shell hook and
+/// installs, so it never fails.
+pub fn record_projects(files: &[PathBuf]) {
+  if let Err(err) = try_record_projects(files) {
+    trace!("Failed to record projects: {err}");
+  }
+}
+
+fn try_record_projects(files: &[PathBuf]) -> Result<()> {
+  let global_files = get_walk_options()?.global_files;
+  let dirs = files
+    .iter()
+    .filter(|file| file.file_name().is_some_and(|name| name == TOOL_VERSIONS))
+    .filter(|file| !global_files.contains(file))
+    .filter_map(|file| file.parent())
+    .collect::<Vec<_>>();
+
+  if dirs.is_empty() {
+    return Ok(());
+  }
+
+  let registry_path = get_data_dir()?.join(PROJECT_REGISTRY);
+  let mut registry = ProjectRegistry::load(&registry_path)?;
+  for dir in dirs {
+    registry.record(dir);
+  }
+
+  registry.remove_missing();
+  registry.save(&registry_path)?;
+  Ok(())
+}
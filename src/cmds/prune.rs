@@ -0,0 +1,195 @@
+use std::{
+  collections::{BTreeMap, HashSet},
+  fs,
+  time::{Duration, SystemTime},
+};
+
+use anyhow::{bail, Result};
+use log::warn;
+
+use crate::{
+  cache::dir_size,
+  cmds::install::uninstall,
+  dirs::{
+    get_data_dir, get_dir, get_plugin_scripts, get_walk_options, INSTALLS_DIR, PROJECT_REGISTRY,
+    TOOL_VERSIONS, TRUST_STORE,
+  },
+  pretty,
+  projects::ProjectRegistry,
+  trust::TrustStore,
+  versions::{Version, Versions},
+};
+
+pub fn prune(dry_run: bool, keep_latest: usize, recent: Duration) -> Result<()> {
+  let referenced = referenced_versions()?;
+  let installs_dir = get_dir(INSTALLS_DIR)?;
+
+  let mut installed = BTreeMap::new();
+  let mut last_used = BTreeMap::new();
+  for entry in fs::read_dir(&installs_dir)? {
+    let plugin = entry?.file_name().to_string_lossy().to_string();
+    let scripts = match get_plugin_scripts(&plugin) {
+      Ok(scripts) => scripts,
+      Err(_) => {
+        warn!("Skipping {plugin}, its plugin is not installed anymore");
+        continue;
+      }
+    };
+
+    let versions = scripts.installed_versions()?;
+    for version in &versions {
+      if let Some(used) = scripts.last_used(&Version::parse(version)) {
+        last_used.insert((plugin.clone(), version.clone()), used);
+      }
+    }
+
+    installed.insert(plugin, versions);
+  }
+
+  let now = SystemTime::now();
+  let unused = select_unused(&installed, &referenced, keep_latest, |plugin, version| {
+    last_used
+      .get(&(plugin.to_owned(), version.to_owned()))
+      .is_some_and(|used| now.duration_since(*used).unwrap_or_default() < recent)
+  });
+
+  if unused.is_empty() {
+    println!("Nothing to prune");
+    return Ok(());
+  }
+
+  let mut freed = 0;
+  let mut failed = 0;
+  for (plugin, version) in &unused {
+    let size = dir_size(&installs_dir.join(plugin).join(version)).unwrap_or_default();
+    if dry_run {
+      println!(
+        "Would remove {} ({})",
+        pretty::plugin_version(plugin, version),
+        pretty::size(size)
+      );
+      freed += size;
+      continue;
+    }
+
+    match uninstall(plugin.to_string(), version.to_string()) {
+      Ok(()) => freed += size,
+      Err(err) => {
+        warn!(
+          "Failed to remove {}: {err}",
+          pretty::plugin_version(plugin, version)
+        );
+        failed += 1;
+      }
+    }
+  }
+
+  if dry_run {
+    println!("Would free {}", pretty::size(freed));
+  } else {
+    println!("Freed {}", pretty::size(freed));
+  }
+
+  if failed > 0 {
+    bail!("Failed to remove {failed} of {} versions", unused.len());
+  }
+
+  Ok(())
+}
+
+/// Every version mentioned in the global versions files, the versions files of
+/// trusted and known projects and the ones that apply to the current directory. Fallback
+/// versions count as well, since they are used as soon as the first one is missing.
+fn referenced_versions() -> Result<HashSet<(String, String)>> {
+  let walk_options = get_walk_options()?;
+  let mut files = Versions::find_all_files(std::env::current_dir()?, TOOL_VERSIONS, &walk_options)?;
+  files.extend(walk_options.global_files);
+
+  // Every project that was trusted is known, even if its files changed since
+  let store = TrustStore::load(get_data_dir()?.join(TRUST_STORE))?;
+  files.extend(
+    store
+      .files()
+      .filter(|path| path.file_name().is_some_and(|name| name == TOOL_VERSIONS)),
+  );
+
+  let registry = ProjectRegistry::load(get_data_dir()?.join(PROJECT_REGISTRY))?;
+  files.extend(registry.dirs().map(|dir| dir.join(TOOL_VERSIONS)));
+
+  files.retain(|path| path.is_file());
+  files.sort();
+  files.dedup();
+
+  // A file that can't be read fails the whole prune, since there's no way to
+  // tell which versions it needs
+  let mut result = HashSet::new();
+  for entry in Versions::entries_from_files(&files)? {
+    for version in entry.versions {
+      result.insert((entry.plugin.clone(), version.version_str().to_owned()));
+    }
+  }
+
+  Ok(result)
+}
+
+/// Pick the installs that are not referenced, not among the `keep_latest` newest
+/// versions of their tool and were not used recently. `installed` has the
+/// versions of every tool from oldest to newest.
+fn select_unused<'a>(
+  installed: &'a BTreeMap<String, Vec<String>>,
+  referenced: &HashSet<(String, String)>,
+  keep_latest: usize,
+  recently_used: impl Fn(&str, &str) -> bool,
+) -> Vec<(&'a str, &'a str)> {
+  let mut result = Vec::new();
+
+  for (plugin, versions) in installed {
+    let prunable = versions.len().saturating_sub(keep_latest);
+    for version in &versions[..prunable] {
+      if referenced.contains(&(plugin.clone(), version.clone())) || recently_used(plugin, version) {
+        continue;
+      }
+
+      result.push((plugin.as_str(), version.as_str()));
+    }
+  }
+
+  result
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn unused_selection() {
+    let installed = BTreeMap::from([
+      (
+        "nodejs".to_owned(),
+        vec!["14.0.0", "16.0.0", "17.0.0", "18.0.0", "19.0.0"]
+          .into_iter()
+          .map(String::from)
+          .collect::<Vec<_>>(),
+      ),
+      ("ruby".to_owned(), vec!["3.1.0".to_owned()]),
+    ]);
+
+    let referenced = HashSet::from([("nodejs".to_owned(), "16.0.0".to_owned())]);
+    let recently_used = |plugin: &str, version: &str| plugin == "nodejs" && version == "17.0.0";
+
+    assert_eq!(
+      select_unused(&installed, &referenced, 0, recently_used),
+      vec![
+        ("nodejs", "14.0.0"),
+        ("nodejs", "18.0.0"),
+        ("nodejs", "19.0.0"),
+        ("ruby", "3.1.0"),
+      ]
+    );
+
+    assert_eq!(
+      select_unused(&installed, &referenced, 2, recently_used),
+      vec![("nodejs", "14.0.0")]
+    );
+  }
+}
@@ -0,0 +1,88 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Result};
+use log::info;
+
+use crate::{
+  dirs::{get_data_dir, DOTENV, PROJECT_CONFIG, TOOL_VERSIONS, TRUST_STORE},
+  trust::{TrustStatus, TrustStore},
+};
+
+pub fn trust(path: Option<String>) -> Result<()> {
+  let files = find_files(path)?;
+  let store_path = get_data_dir()?.join(TRUST_STORE);
+  let mut store = TrustStore::load(&store_path)?;
+
+  for file in files {
+    store.trust(&file)?;
+    info!("Trusted {}", file.to_string_lossy());
+  }
+
+  store.save(&store_path)?;
+  Ok(())
+}
+
+pub fn untrust(path: Option<String>) -> Result<()> {
+  let files = find_files(path)?;
+  let store_path = get_data_dir()?.join(TRUST_STORE);
+  let mut store = TrustStore::load(&store_path)?;
+
+  for file in files {
+    if store.untrust(&file) {
+      info!("Untrusted {}", file.to_string_lossy());
+    } else {
+      info!("{} was not trusted", file.to_string_lossy());
+    }
+  }
+
+  store.save(&store_path)?;
+  Ok(())
+}
+
+/// Change the file at `path` with `write`. A file that was trusted stays trusted,
+/// since the only change is the one the user just asked for.
+pub fn write_keeping_trust(path: &Path, write: impl FnOnce() -> Result<()>) -> Result<()> {
+  let store_path = get_data_dir()?.join(TRUST_STORE);
+  let mut store = TrustStore::load(&store_path)?;
+  let trusted = matches!(store.status(path), Ok(TrustStatus::Trusted));
+
+  write()?;
+
+  if trusted {
+    store.trust(path)?;
+    store.save(&store_path)?;
+  }
+
+  Ok(())
+}
+
+/// A path to a file is used as is, while for a directory, all files that can
+/// change the env are used. Defaults to the current directory.
+fn find_files(path: Option<String>) -> Result<Vec<PathBuf>> {
+  let path = match path {
+    Some(path) => PathBuf::from(path),
+    None => std::env::current_dir()?,
+  };
+
+  if path.is_file() {
+    return Ok(vec![path]);
+  }
+
+  let files = find_files_in_dir(&path);
+  if files.is_empty() {
+    bail!(
+      "No `{TOOL_VERSIONS}`, `{PROJECT_CONFIG}` or `{DOTENV}` files found in {}",
+      path.to_string_lossy()
+    );
+  }
+
+  Ok(files)
+}
+
+fn find_files_in_dir(dir: &Path) -> Vec<PathBuf> {
+  [TOOL_VERSIONS, PROJECT_CONFIG, DOTENV]
+    .iter()
+    .map(|filename| dir.join(filename))
+    .filter(|path| path.is_file())
+    .collect()
+}
@@ -0,0 +1,204 @@
+use std::{fs, path::PathBuf};
+
+use anyhow::{bail, Result};
+use console::style;
+use log::{info, warn};
+
+use crate::{
+  cache::CacheLimits,
+  cmds::{self, install::install_tool, trust::write_keeping_trust},
+  dirs::{get_global_tool_versions, get_plugin_scripts, get_walk_options, TOOL_VERSIONS},
+  pretty,
+  scripts::is_stable,
+  versions::{bump_between, compare_versions, Bump, Version, Versions},
+};
+
+struct Upgrade {
+  plugin: String,
+  from: Vec<Version>,
+  to: Vec<Version>,
+  line: usize,
+}
+
+pub fn upgrade(tools: Vec<String>, level: Bump, global: bool, dry_run: bool) -> Result<()> {
+  let path = versions_file(global)?;
+  let content = fs::read_to_string(&path)?;
+  let entries = Versions::entries_from_files(std::slice::from_ref(&path))?;
+
+  for tool in &tools {
+    if !entries.iter().any(|entry| &entry.plugin == tool) {
+      bail!("Tool `{tool}` is not defined in {}", path.to_string_lossy());
+    }
+  }
+
+  let mut upgrades = Vec::new();
+  for entry in entries {
+    if !tools.is_empty() && !tools.contains(&entry.plugin) {
+      continue;
+    }
+
+    let (plugin, from, line) = (entry.plugin, entry.versions, entry.line);
+
+    // Only the pinned version is upgraded, fallbacks after it stay as they are
+    let pinned = match from.first() {
+      Some(Version::Remote(pinned)) => pinned.clone(),
+      _ => {
+        info!("Skipping {plugin}, it isn't pinned to a release");
+        continue;
+      }
+    };
+
+    let scripts = get_plugin_scripts(&plugin)?;
+    let newest = scripts
+      .list_all()?
+      .into_iter()
+      .filter(|version| is_stable(version))
+      .filter(|version| bump_between(&pinned, version).is_some_and(|bump| bump <= level))
+      .max_by(|a, b| compare_versions(a, b));
+
+    if let Some(newest) = newest {
+      let mut to = from.clone();
+      to[0] = Version::Remote(newest);
+      upgrades.push(Upgrade {
+        plugin,
+        from,
+        to,
+        line,
+      });
+    }
+  }
+
+  if upgrades.is_empty() {
+    println!("Everything in {} is up to date", path.to_string_lossy());
+    return Ok(());
+  }
+
+  let lines = content.split('\n').collect::<Vec<_>>();
+  println!("{}", style(path.to_string_lossy()).bold());
+  for upgrade in &upgrades {
+    let line = lines[upgrade.line - 1];
+    println!("{}", style(format!("- {line}")).red());
+    println!(
+      "{}",
+      style(format!(
+        "+ {}",
+        replace_entry(line, &upgrade.plugin, &upgrade.to)
+      ))
+      .green()
+    );
+  }
+
+  if dry_run {
+    return Ok(());
+  }
+
+  // Only pin versions that could be installed, so the file never points to
+  // versions that don't work
+  let mut failed = 0;
+  let mut installed = Vec::new();
+  for upgrade in &upgrades {
+    if let Err(err) = install_tool(&upgrade.plugin, &upgrade.to[0], None, None, false) {
+      warn!(
+        "Failed to install {}, keeping {}: {err}",
+        pretty::plugin_version(&upgrade.plugin, upgrade.to[0].version_str()),
+        upgrade.from[0].raw()
+      );
+      failed += 1;
+      continue;
+    }
+
+    installed.push(upgrade);
+  }
+
+  cmds::cache::auto_prune(&CacheLimits::from_env()?);
+
+  if !installed.is_empty() {
+    let content = upgrade_lines(&content, &installed);
+    write_keeping_trust(&path, || Ok(fs::write(&path, content)?))?;
+  }
+
+  if failed > 0 {
+    bail!("Failed to upgrade {failed} of {} tools", upgrades.len());
+  }
+
+  Ok(())
+}
+
+/// The versions file to upgrade, either the global one or the closest one.
+fn versions_file(global: bool) -> Result<PathBuf> {
+  if global {
+    let path = get_global_tool_versions()?;
+    if !path.is_file() {
+      bail!(
+        "Global versions file {} does not exist",
+        path.to_string_lossy()
+      );
+    }
+
+    return Ok(path);
+  }
+
+  let files = Versions::find_all_files(
+    std::env::current_dir()?,
+    TOOL_VERSIONS,
+    &get_walk_options()?,
+  )?;
+
+  match files.into_iter().next() {
+    Some(path) => Ok(path),
+    None => bail!("No versions file found"),
+  }
+}
+
+/// Apply `upgrades` to the contents of a versions file. Only the upgraded lines
+/// change, everything else is kept as it was.
+fn upgrade_lines(content: &str, upgrades: &[&Upgrade]) -> String {
+  content
+    .split('\n')
+    .enumerate()
+    .map(
+      |(i, line)| match upgrades.iter().find(|upgrade| upgrade.line == i + 1) {
+        Some(upgrade) => replace_entry(line, &upgrade.plugin, &upgrade.to),
+        None => line.to_owned(),
+      },
+    )
+    .collect::<Vec<_>>()
+    .join("\n")
+}
+
+/// Replace the entry on a line of a versions file, keeping its indentation and
+/// any comment after it.
+fn replace_entry(line: &str, plugin: &str, versions: &[Version]) -> String {
+  let (entry, comment) = match line.find('#') {
+    Some(i) => line.split_at(i),
+    None => (line, ""),
+  };
+
+  let indent = &entry[..entry.len() - entry.trim_start().len()];
+  let spacing = &entry[entry.trim_end().len()..];
+  let versions = versions.iter().map(Version::raw).collect::<Vec<_>>();
+  format!("{indent}{plugin} {}{spacing}{comment}", versions.join(" "))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn upgrades_keep_the_rest_of_the_file() {
+    let content = "# Pinned tools\nnodejs 16.0.0 system # LTS\n\n  python 3.9.0\nruby 3.0.0\n";
+    let upgrade = |plugin: &str, to: &[&str], line| Upgrade {
+      plugin: plugin.to_owned(),
+      from: Vec::new(),
+      to: to.iter().map(|version| Version::parse(version)).collect(),
+      line,
+    };
+
+    let nodejs = upgrade("nodejs", &["16.1.0", "system"], 2);
+    let python = upgrade("python", &["3.9.7"], 4);
+    assert_eq!(
+      upgrade_lines(content, &[&nodejs, &python]),
+      "# Pinned tools\nnodejs 16.1.0 system # LTS\n\n  python 3.9.7\nruby 3.0.0\n"
+    );
+  }
+}
@@ -1,5 +1,6 @@
-use std::io::Write;
+use std::{io::Write, path::PathBuf};
 
+use anyhow::{bail, Result};
 use clap::IntoApp;
 
 use crate::Cli;
@@ -12,3 +13,17 @@ pub fn print_help_and_exit() -> ! {
   let _ = std::io::stderr().lock().flush();
   std::process::exit(2);
 }
+
+/// Resolve an optional directory argument, defaulting to the current directory.
+pub fn resolve_dir(dir: Option<String>) -> Result<PathBuf> {
+  let dir = match dir {
+    Some(dir) => PathBuf::from(dir),
+    None => std::env::current_dir()?,
+  };
+
+  if !dir.is_dir() {
+    bail!("Directory {} does not exist", dir.to_string_lossy());
+  }
+
+  Ok(dir.canonicalize()?)
+}
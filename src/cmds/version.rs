@@ -1,14 +1,15 @@
-use std::path::PathBuf;
+use std::{fs, path::PathBuf};
 
-use anyhow::{anyhow, bail, Result};
+use anyhow::{bail, Result};
 
 use crate::{
-  dirs::{get_plugin_scripts, TOOL_VERSIONS},
+  cmds::trust::write_keeping_trust,
+  dirs::{get_global_tool_versions, get_plugin_scripts, TOOL_VERSIONS},
   shell::{Bash, Shell, ShellState},
-  versions::Versions,
+  versions::{version_override_var, Versions},
 };
 
-fn use_version_for_dir(name: String, version: String, path: PathBuf) -> Result<()> {
+fn use_version_for_file(name: String, version: String, path: PathBuf) -> Result<()> {
   let scripts = get_plugin_scripts(&name)?;
   // TODO: errors
   let version = scripts.resolve(&version)?.unwrap();
@@ -20,26 +21,26 @@ fn use_version_for_dir(name: String, version: String, path: PathBuf) -> Result<(
     );
   }
 
-  let global_versions_path = path.join(TOOL_VERSIONS);
-  let mut versions = if global_versions_path.is_file() {
-    Versions::find(&path, TOOL_VERSIONS)?
+  let mut versions = if path.is_file() {
+    Versions::parse(&fs::read_to_string(&path)?)?
   } else {
     Versions::new()
   };
 
-  versions.insert(name, vec![version]);
-  versions.save(&global_versions_path)?;
+  if let Some(parent) = path.parent() {
+    fs::create_dir_all(parent)?;
+  }
 
-  Ok(())
+  versions.insert(name, vec![version]);
+  write_keeping_trust(&path, || Ok(versions.save(&path)?))
 }
 
 pub fn global(name: String, version: String) -> Result<()> {
-  let home_dir = dirs::home_dir().ok_or_else(|| anyhow!("Failed to get home dir"))?;
-  use_version_for_dir(name, version, home_dir)
+  use_version_for_file(name, version, get_global_tool_versions()?)
 }
 
 pub fn local(name: String, version: String) -> Result<()> {
-  use_version_for_dir(name, version, std::env::current_dir()?)
+  use_version_for_file(name, version, std::env::current_dir()?.join(TOOL_VERSIONS))
 }
 
 pub fn shell(name: String, version: String) -> Result<()> {
@@ -57,6 +58,8 @@ pub fn shell(name: String, version: String) -> Result<()> {
   let env = scripts.get_env(&version)?;
   let mut state = ShellState::new();
 
+  // The override keeps the version when the shell hook updates the env
+  state.set(&version_override_var(&name), &version.raw());
   for (key, val) in &env.vars {
     state.set(key, val);
   }
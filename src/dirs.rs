@@ -2,17 +2,30 @@ use std::{fs, path::PathBuf};
 
 use anyhow::{anyhow, bail, Result};
 
-use crate::scripts::PluginScripts;
+use crate::{hooks::UserHooks, scripts::PluginScripts, versions::WalkOptions};
 
 pub const REGISTRIES_DIR: &str = "registries";
 pub const PLUGINS_DIR: &str = "plugins";
 pub const INSTALLS_DIR: &str = "installs";
 pub const DOWNLOADS_DIR: &str = "downloads";
 pub const BIN_DIR: &str = "bin";
+pub const EXEC_ENV_CACHE_DIR: &str = "cache/exec-env";
+pub const LOGS_DIR: &str = "logs";
 
 pub const TOOL_VERSIONS: &str = ".tool-versions";
+pub const GLOBAL_TOOL_VERSIONS: &str = "tool-versions";
+pub const PROJECT_CONFIG: &str = ".qwer.toml";
+pub const DOTENV: &str = ".env";
+
+pub const TRUST_STORE: &str = "trust.toml";
+pub const PROJECT_REGISTRY: &str = "projects.toml";
+
+pub const HOOKS_DIR: &str = "hooks";
 
 const DATA_DIR: &str = "qwer";
+const CONFIG_DIR: &str = "qwer";
+
+const QWER_GLOBAL_TOOL_VERSIONS: &str = "QWER_GLOBAL_TOOL_VERSIONS";
 
 pub fn get_data_dir() -> Result<PathBuf> {
   let data_dir = dirs::data_dir().ok_or_else(|| anyhow!("failed to get data dir"))?;
@@ -28,17 +41,50 @@ pub fn get_dir(dir: &str) -> Result<PathBuf> {
   Ok(subdir)
 }
 
-pub fn _get_global_tool_versions() -> Result<PathBuf> {
+pub fn get_config_dir() -> Result<PathBuf> {
+  let config_dir = dirs::config_dir().ok_or_else(|| anyhow!("failed to get config dir"))?;
+  Ok(config_dir.join(CONFIG_DIR))
+}
+
+/// The asdf-compatible global versions file at `~/.tool-versions`.
+pub fn get_home_tool_versions() -> Result<PathBuf> {
   let home_dir = dirs::home_dir().ok_or_else(|| anyhow!("failed to get home dir"))?;
   Ok(home_dir.join(TOOL_VERSIONS))
 }
 
+/// The global versions file in the config dir, e.g. `~/.config/qwer/tool-versions`.
+pub fn get_xdg_tool_versions() -> Result<PathBuf> {
+  Ok(get_config_dir()?.join(GLOBAL_TOOL_VERSIONS))
+}
+
+/// The global versions file that `qwer global` writes to. This is `~/.tool-versions`
+/// unless `QWER_GLOBAL_TOOL_VERSIONS` is set to `xdg`.
+pub fn get_global_tool_versions() -> Result<PathBuf> {
+  match std::env::var(QWER_GLOBAL_TOOL_VERSIONS).as_deref() {
+    Ok("xdg") => get_xdg_tool_versions(),
+    Ok("home") | Err(_) => get_home_tool_versions(),
+    Ok(other) => {
+      bail!("Invalid value `{other}` for {QWER_GLOBAL_TOOL_VERSIONS}, expected `home` or `xdg`")
+    }
+  }
+}
+
+/// Build the options for the versions file walk. Both global versions files are
+/// always included at the lowest precedence, with the config dir one coming last.
+pub fn get_walk_options() -> Result<WalkOptions> {
+  let mut options = WalkOptions::from_env();
+  options.global_files = vec![get_home_tool_versions()?, get_xdg_tool_versions()?];
+  Ok(options)
+}
+
 pub fn get_plugin_scripts(name: &str) -> Result<PluginScripts> {
   let result = PluginScripts::new(
     name,
     &get_dir(PLUGINS_DIR)?,
     &get_dir(INSTALLS_DIR)?,
     &get_dir(DOWNLOADS_DIR)?,
+    &get_dir(EXEC_ENV_CACHE_DIR)?,
+    &get_dir(LOGS_DIR)?,
     &[&get_dir(BIN_DIR)?.to_string_lossy()],
   )?;
 
@@ -48,3 +94,8 @@ pub fn get_plugin_scripts(name: &str) -> Result<PluginScripts> {
 
   Ok(result)
 }
+
+/// The hooks the user configured for a tool in `$XDG_CONFIG_HOME/qwer/hooks/<tool>`.
+pub fn get_user_hooks(name: &str) -> Result<UserHooks> {
+  Ok(UserHooks::new(get_config_dir()?.join(HOOKS_DIR).join(name)))
+}
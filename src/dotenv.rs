@@ -0,0 +1,321 @@
+use std::{
+  fs, io,
+  iter::Peekable,
+  path::{Path, PathBuf},
+  str::Chars,
+};
+
+use log::trace;
+use thiserror::Error;
+
+use crate::versions::{Versions, VersionsError, WalkOptions};
+
+/// A parsed var with the line it starts on, its name and its value.
+pub type Var = (usize, String, String);
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum ParseError {
+  #[error("line {0}: expected `KEY=value`")]
+  MissingEquals(usize),
+
+  #[error("line {0}: `{1}` is not a valid variable name")]
+  InvalidKey(usize, String),
+
+  #[error("line {0}: quoted value is never closed")]
+  Unterminated(usize),
+
+  #[error("line {0}: unexpected characters after quoted value")]
+  TrailingCharacters(usize),
+}
+
+#[derive(Error, Debug)]
+pub enum DotenvError {
+  #[error("failed to parse dotenv file `{0}`: {1}")]
+  Parse(PathBuf, ParseError),
+
+  #[error("error while looking for dotenv files: {0}")]
+  Versions(#[from] VersionsError),
+
+  #[error("io error while reading dotenv file: {0}")]
+  Io(#[from] io::Error),
+}
+
+/// Parse the contents of a dotenv file. Supports `export` prefixes, comments,
+/// unquoted values, single quoted values which are taken literally, and double
+/// quoted values with escapes. Quoted values can span multiple lines. Every var
+/// is returned together with the line it starts on.
+///
+/// # Examples
+///
+/// ```
+/// use qwer::dotenv::parse;
+///
+/// let vars = parse("export FOO=\"bar\\nbaz\" # comment").unwrap();
+/// assert_eq!(vars, vec![(1, "FOO".to_owned(), "bar\nbaz".to_owned())]);
+/// ```
+pub fn parse(content: &str) -> Result<Vec<Var>, ParseError> {
+  let mut result = Vec::new();
+  let mut chars = content.chars().peekable();
+  let mut line = 1;
+
+  loop {
+    skip_blank(&mut chars, &mut line);
+    if chars.peek().is_none() {
+      return Ok(result);
+    }
+
+    if chars.peek() == Some(&'#') {
+      skip_line(&mut chars);
+      continue;
+    }
+
+    let start_line = line;
+    let mut key = take_while(&mut chars, |c| c != '=' && c != '\n');
+    if chars.next() != Some('=') {
+      return Err(ParseError::MissingEquals(start_line));
+    }
+
+    if let Some(stripped) = key.strip_prefix("export ") {
+      key = stripped.to_owned();
+    }
+
+    let key = key.trim().to_owned();
+    if !is_valid_key(&key) {
+      return Err(ParseError::InvalidKey(start_line, key));
+    }
+
+    while chars.next_if(|c| *c == ' ' || *c == '\t').is_some() {}
+
+    let val = match chars.peek() {
+      Some('\'') => {
+        chars.next();
+        let val = take_quoted(&mut chars, &mut line, '\'', start_line)?;
+        end_of_value(&mut chars, start_line)?;
+        val
+      }
+      Some('"') => {
+        chars.next();
+        let val = take_quoted(&mut chars, &mut line, '"', start_line)?;
+        end_of_value(&mut chars, start_line)?;
+        val
+      }
+      _ => take_unquoted(&mut chars),
+    };
+
+    result.push((start_line, key, val));
+  }
+}
+
+fn is_valid_key(key: &str) -> bool {
+  let mut chars = key.chars();
+  match chars.next() {
+    Some(c) if c.is_ascii_alphabetic() || c == '_' => {}
+    _ => return false,
+  }
+
+  chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+}
+
+fn skip_blank(chars: &mut Peekable<Chars>, line: &mut usize) {
+  while let Some(c) = chars.next_if(|c| c.is_whitespace()) {
+    if c == '\n' {
+      *line += 1;
+    }
+  }
+}
+
+fn skip_line(chars: &mut Peekable<Chars>) {
+  while chars.next_if(|c| *c != '\n').is_some() {}
+}
+
+fn take_while(chars: &mut Peekable<Chars>, predicate: impl Fn(char) -> bool) -> String {
+  let mut result = String::new();
+  while let Some(c) = chars.next_if(|c| predicate(*c)) {
+    result.push(c);
+  }
+
+  result
+}
+
+fn take_unquoted(chars: &mut Peekable<Chars>) -> String {
+  let raw = take_while(chars, |c| c != '\n');
+
+  // A `#` only starts a comment if it is preceded by whitespace
+  let end = raw
+    .char_indices()
+    .find(|(i, c)| *c == '#' && raw[..*i].ends_with(|c: char| c.is_whitespace()))
+    .map(|(i, _)| i)
+    .unwrap_or(raw.len());
+
+  raw[..end].trim().to_owned()
+}
+
+fn take_quoted(
+  chars: &mut Peekable<Chars>,
+  line: &mut usize,
+  quote: char,
+  start_line: usize,
+) -> Result<String, ParseError> {
+  let mut result = String::new();
+
+  loop {
+    let c = chars.next().ok_or(ParseError::Unterminated(start_line))?;
+    if c == '\n' {
+      *line += 1;
+    }
+
+    if c == quote {
+      return Ok(result);
+    }
+
+    if c == '\\' && quote == '"' {
+      let escaped = chars.next().ok_or(ParseError::Unterminated(start_line))?;
+      match escaped {
+        'n' => result.push('\n'),
+        'r' => result.push('\r'),
+        't' => result.push('\t'),
+        '\n' => *line += 1,
+        other => result.push(other),
+      }
+
+      continue;
+    }
+
+    result.push(c);
+  }
+}
+
+fn end_of_value(chars: &mut Peekable<Chars>, start_line: usize) -> Result<(), ParseError> {
+  let rest = take_while(chars, |c| c != '\n');
+  let rest = rest.trim();
+  if rest.is_empty() || rest.starts_with('#') {
+    Ok(())
+  } else {
+    Err(ParseError::TrailingCharacters(start_line))
+  }
+}
+
+/// Find all dotenv files by walking up from `workdir`, skipping files rejected by
+/// `filter`, and parse each. Closer files come first, since they take precedence
+/// over ones further up.
+pub fn find_dotenv_files<P: AsRef<Path>>(
+  workdir: P,
+  filename: &str,
+  options: &WalkOptions,
+  filter: impl Fn(&Path) -> bool,
+) -> Result<Vec<(PathBuf, Vec<Var>)>, DotenvError> {
+  let mut result = Vec::new();
+
+  for path in Versions::find_all_files(workdir, filename, options)? {
+    if !filter(&path) {
+      continue;
+    }
+
+    trace!("Loading dotenv file {path:?}");
+    let contents = fs::read_to_string(&path)?;
+    let vars = parse(&contents).map_err(|err| DotenvError::Parse(path.clone(), err))?;
+    result.push((path, vars));
+  }
+
+  Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn vars(entries: &[(usize, &str, &str)]) -> Vec<Var> {
+    entries
+      .iter()
+      .map(|(line, key, val)| (*line, key.to_string(), val.to_string()))
+      .collect()
+  }
+
+  #[test]
+  fn parse_values() {
+    let content = r#"
+# comment
+PLAIN=value
+export EXPORTED=yes
+SPACED = some value   # trailing comment
+HASH=foo#bar
+EMPTY=
+SINGLE='$HOME \n # not a comment'
+DOUBLE="line\nbreak \"quoted\" \\ end" # comment
+MULTI="first
+second"
+"#;
+
+    assert_eq!(
+      parse(content).expect("failed to parse"),
+      vars(&[
+        (3, "PLAIN", "value"),
+        (4, "EXPORTED", "yes"),
+        (5, "SPACED", "some value"),
+        (6, "HASH", "foo#bar"),
+        (7, "EMPTY", ""),
+        (8, "SINGLE", "$HOME \\n # not a comment"),
+        (9, "DOUBLE", "line\nbreak \"quoted\" \\ end"),
+        (10, "MULTI", "first\nsecond"),
+      ])
+    );
+  }
+
+  #[test]
+  fn parse_errors() {
+    assert_eq!(parse("\nFOO"), Err(ParseError::MissingEquals(2)));
+    assert_eq!(
+      parse("1FOO=bar"),
+      Err(ParseError::InvalidKey(1, "1FOO".to_owned()))
+    );
+    assert_eq!(
+      parse("A=1\nFOO=\"bar\nbaz"),
+      Err(ParseError::Unterminated(2))
+    );
+    assert_eq!(
+      parse("FOO='bar' baz"),
+      Err(ParseError::TrailingCharacters(1))
+    );
+  }
+
+  #[test]
+  fn line_numbers_after_multiline_values() {
+    assert_eq!(parse("A=\"1\n2\"\nB"), Err(ParseError::MissingEquals(3)));
+  }
+
+  #[test]
+  fn closer_files_take_precedence() {
+    let workdir = tempfile::tempdir().expect("failed to create temp dir");
+    let subdir = workdir.as_ref().join("sub");
+    fs::create_dir_all(&subdir).expect("failed to create dirs");
+    fs::write(workdir.as_ref().join(".env"), "FOO=outer\nBAR=outer").expect("failed to write");
+    fs::write(subdir.join(".env"), "FOO=inner").expect("failed to write");
+
+    let files = find_dotenv_files(&subdir, ".env", &WalkOptions::default(), |_| true)
+      .expect("failed to load");
+    assert_eq!(
+      files,
+      vec![
+        (subdir.join(".env"), vars(&[(1, "FOO", "inner")])),
+        (
+          workdir.as_ref().join(".env"),
+          vars(&[(1, "FOO", "outer"), (2, "BAR", "outer")])
+        ),
+      ]
+    );
+  }
+
+  #[test]
+  fn repeated_keys_keep_their_order() {
+    let workdir = tempfile::tempdir().expect("failed to create temp dir");
+    fs::write(workdir.as_ref().join(".env"), "FOO=first\nFOO=second").expect("failed to write");
+
+    // The vars are applied in order, so the last value of a key wins
+    let files = find_dotenv_files(&workdir, ".env", &WalkOptions::default(), |_| true)
+      .expect("failed to load");
+    assert_eq!(
+      files[0].1,
+      vars(&[(1, "FOO", "first"), (2, "FOO", "second")])
+    );
+  }
+}
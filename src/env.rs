@@ -1,10 +1,11 @@
 use std::{
-  collections::{BTreeMap, BTreeSet, HashSet},
+  collections::{BTreeMap, HashSet},
   hash::Hasher,
-  io::{Read, Write},
+  io::Read,
 };
 
 use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
 lazy_static! {
@@ -43,17 +44,32 @@ lazy_static! {
     ]);
 }
 
-#[derive(Debug, Default)]
+/// Version of the format written by [`Env::serialize`]. Bump it whenever the
+/// fields of [`Env`] change.
+const FORMAT_VERSION: u32 = 2;
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct Env {
-  pub path: BTreeSet<String>,
+  /// Path entries in order of precedence, without duplicates. Use
+  /// [`Env::add_path`] to add entries so they stay unique.
+  pub path: Vec<String>,
   pub vars: BTreeMap<String, String>,
 }
 
 #[derive(Debug, Error)]
 pub enum EnvError {
-  #[error("env strings must contain 2 segments")]
+  #[error("env string is not in a known format")]
   InvalidEnvString,
 
+  #[error("env string uses unsupported format `{0}`")]
+  UnsupportedVersion(String),
+
+  #[error("failed to decompress env string: {0}")]
+  DecompressFailed(#[from] snap::Error),
+
+  #[error("failed to decode env: {0}")]
+  InvalidJson(#[from] serde_json::Error),
+
   #[error("string was not valid utf-8: {0}")]
   InvalidString(#[from] std::string::FromUtf8Error),
 
@@ -65,82 +81,118 @@ pub enum EnvError {
 }
 
 impl Env {
+  /// Append a path entry with lower precedence than all existing ones. Entries
+  /// that are already present keep their position.
+  pub fn add_path<S: Into<String>>(&mut self, entry: S) {
+    let entry = entry.into();
+    if !self.path.contains(&entry) {
+      self.path.push(entry);
+    }
+  }
+
+  /// Merge another env into this one. Path entries from `other` have lower
+  /// precedence, while its vars overwrite existing ones.
   pub fn merge(&mut self, other: Env) {
-    self.path.extend(other.path);
+    for entry in other.path {
+      self.add_path(entry);
+    }
 
     for (key, val) in other.vars {
       self.vars.insert(key, val);
     }
   }
 
+  /// Merge another env into this one with lower precedence. Path entries from
+  /// `other` are appended, and its vars are only used if they aren't set yet.
+  pub fn merge_lower(&mut self, other: Env) {
+    for entry in other.path {
+      self.add_path(entry);
+    }
+
+    for (key, val) in other.vars {
+      self.vars.entry(key).or_insert(val);
+    }
+  }
+
+  /// Encode the env into a string that can be stored in an env var. The string is
+  /// tagged with the format version, so strings written by other versions of qwer
+  /// can be detected instead of being misread.
   pub fn serialize(&self) -> String {
-    let vars_str = self
-      .vars
-      .iter()
-      // It's fine if = appears in the var, since we'll split_once
-      // later instead of splitting at every =
-      .map(|(key, val)| format!("{}={}", key, base64::encode(val)))
-      .collect::<Vec<_>>()
-      .join("\n");
-
-    let vars_writer = base64::write::EncoderStringWriter::new(base64::STANDARD_NO_PAD);
-    let mut vars_writer = snap::write::FrameEncoder::new(vars_writer);
-    vars_writer
-      .write_all(vars_str.as_bytes())
-      .expect("Failed to write vars");
-
-    let path_str = self.path.iter().cloned().collect::<Vec<_>>().join("\n");
-    let path_writer = base64::write::EncoderStringWriter::new(base64::STANDARD_NO_PAD);
-    let mut path_writer = snap::write::FrameEncoder::new(path_writer);
-    path_writer
-      .write_all(path_str.as_bytes())
-      .expect("Failed to write path");
-
-    [
-      vars_writer
-        .into_inner()
-        .expect("Failed to flush vars")
-        .into_inner(),
-      path_writer
-        .into_inner()
-        .expect("Failed to flush path")
-        .into_inner(),
-    ]
-    .map(base64::encode)
-    .join(".")
+    let json = serde_json::to_vec(self).expect("Failed to encode env");
+    let compressed = snap::raw::Encoder::new()
+      .compress_vec(&json)
+      .expect("Failed to compress env");
+
+    format!(
+      "v{FORMAT_VERSION}:{}",
+      base64::encode_config(compressed, base64::STANDARD_NO_PAD)
+    )
   }
 
   pub fn deserialize(from: &str) -> Result<Self, EnvError> {
+    // base64 never contains a `:`, so untagged strings are from the legacy format
+    let env = match from.split_once(':') {
+      Some((version, data)) if version == format!("v{FORMAT_VERSION}") => {
+        let compressed = base64::decode_config(data, base64::STANDARD_NO_PAD)?;
+        let json = snap::raw::Decoder::new().decompress_vec(&compressed)?;
+        serde_json::from_slice::<Env>(&json)?
+      }
+      Some((version, _)) => return Err(EnvError::UnsupportedVersion(version.to_owned())),
+      None => Self::deserialize_legacy(from)?,
+    };
+
+    // Keep the path invariants, even if the string was written by hand
+    let mut result = Self {
+      vars: env.vars,
+      path: Vec::new(),
+    };
+
+    for entry in env.path.into_iter().filter(|entry| !entry.is_empty()) {
+      result.add_path(entry);
+    }
+
+    Ok(result)
+  }
+
+  /// Check whether a serialized env uses a format that should be rewritten.
+  pub fn is_legacy(from: &str) -> bool {
+    !from.starts_with(&format!("v{FORMAT_VERSION}:"))
+  }
+
+  /// Read the untagged format used before versioning: snappy compressed vars and
+  /// path, each base64 encoded twice and joined by a `.`.
+  fn deserialize_legacy(from: &str) -> Result<Self, EnvError> {
     let parts = from.split('.').collect::<Vec<_>>();
     if parts.len() != 2 {
       return Err(EnvError::InvalidEnvString);
     }
 
-    let mut vars_reader = StringReader::new(parts[0]);
+    let vars_part = String::from_utf8(base64::decode(parts[0])?)?;
+    let mut vars_reader = StringReader::new(&vars_part);
     let vars_reader = base64::read::DecoderReader::new(&mut vars_reader, base64::STANDARD_NO_PAD);
     let mut vars_reader = snap::read::FrameDecoder::new(vars_reader);
     let mut vars_str = String::new();
     vars_reader.read_to_string(&mut vars_str)?;
     let mut vars = BTreeMap::new();
 
-    for entry in vars_str.split('\n') {
+    // An env without vars was written as an empty string
+    for entry in vars_str.split('\n').filter(|entry| !entry.is_empty()) {
       let (key, val) = entry.split_once('=').ok_or(EnvError::InvalidEnvString)?;
       let decoded_val = String::from_utf8(base64::decode(val)?)?;
       vars.insert(key.to_owned(), decoded_val);
     }
 
-    let mut path_reader = StringReader::new(parts[1]);
+    let path_part = String::from_utf8(base64::decode(parts[1])?)?;
+    let mut path_reader = StringReader::new(&path_part);
     let path_reader = base64::read::DecoderReader::new(&mut path_reader, base64::STANDARD_NO_PAD);
     let mut path_reader = snap::read::FrameDecoder::new(path_reader);
     let mut path_str = String::new();
     path_reader.read_to_string(&mut path_str)?;
 
-    let path = path_str
-      .split('\n')
-      .map(|entry| entry.to_owned())
-      .collect::<BTreeSet<String>>();
-
-    Ok(Self { vars, path })
+    Ok(Self {
+      vars,
+      path: path_str.split('\n').map(|entry| entry.to_owned()).collect(),
+    })
   }
 
   pub fn hash(&self) -> u64 {
@@ -182,3 +234,140 @@ impl<'a> std::io::Read for StringReader<'a> {
     Ok(buf.len())
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn merge_keeps_path_order() {
+    let mut env = Env::default();
+    env.add_path("/b");
+    env.add_path("/a");
+
+    let mut other = Env::default();
+    other.add_path("/c");
+    other.add_path("/b");
+    env.merge(other);
+
+    assert_eq!(env.path, vec!["/b", "/a", "/c"]);
+  }
+
+  #[test]
+  fn serialize_is_stable() {
+    let build = || {
+      let mut env = Env::default();
+      env.add_path("/z/bin");
+      env.add_path("/a/bin");
+      env.vars.insert("FOO".to_owned(), "bar".to_owned());
+      env
+    };
+
+    assert_eq!(build().serialize(), build().serialize());
+    assert_eq!(build().hash(), build().hash());
+
+    let mut reordered = Env::default();
+    reordered.add_path("/a/bin");
+    reordered.add_path("/z/bin");
+    reordered.vars.insert("FOO".to_owned(), "bar".to_owned());
+    assert_ne!(build().hash(), reordered.hash());
+  }
+
+  #[test]
+  fn serialize_roundtrip() {
+    let mut env = Env::default();
+    env.add_path("/z/bin");
+    env.add_path("/a/bin");
+    env.vars.insert("FOO".to_owned(), "bar=baz".to_owned());
+
+    let deserialized = Env::deserialize(&env.serialize()).expect("failed to deserialize");
+    assert_eq!(deserialized.path, env.path);
+    assert_eq!(deserialized.vars, env.vars);
+  }
+
+  #[test]
+  fn serialize_empty() {
+    let env = Env::default();
+    let deserialized = Env::deserialize(&env.serialize()).expect("failed to deserialize");
+    assert!(deserialized.path.is_empty());
+    assert!(deserialized.vars.is_empty());
+  }
+
+  #[test]
+  fn serialize_roundtrip_generated() {
+    // A tiny xorshift generator, so failures are reproducible
+    struct Rng(u64);
+
+    impl Rng {
+      fn next(&mut self, bound: usize) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0 as usize % bound
+      }
+
+      fn string(&mut self, max_len: usize) -> String {
+        let chars = [
+          'a', 'Z', '0', '_', '=', '.', ':', '/', ' ', '\n', '\'', '"', '$', 'ü', 'ß', '日', '👋',
+        ];
+
+        let len = self.next(max_len + 1);
+        (0..len).map(|_| chars[self.next(chars.len())]).collect()
+      }
+    }
+
+    let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+    for _ in 0..500 {
+      let mut env = Env::default();
+      for _ in 0..rng.next(5) {
+        let key = format!("VAR_{}", rng.string(4));
+        let val = rng.string(12);
+        env.vars.insert(key, val);
+      }
+
+      for _ in 0..rng.next(5) {
+        let entry = rng.string(12);
+        if !entry.is_empty() && !entry.contains('\n') {
+          env.add_path(entry);
+        }
+      }
+
+      let serialized = env.serialize();
+      assert!(!Env::is_legacy(&serialized));
+
+      let deserialized = Env::deserialize(&serialized).expect("failed to deserialize");
+      assert_eq!(deserialized.path, env.path);
+      assert_eq!(deserialized.vars, env.vars);
+    }
+  }
+
+  #[test]
+  fn deserialize_legacy() {
+    // Written by the untagged format before it was versioned
+    let legacy = "L3dZQUFITk9ZVkJ3V1FFdUFBQVVxY0xoUms5UFBWbHRSbmxRVjBwb1pXYzlQUXBIVWtWRlZFbE9SejFhTTB4RWRrMVBabHBUUkhkdU5VZE0=.L3dZQUFITk9ZVkJ3V1FFbEFBQU9Sd1hHTDI5d2RDOXViMlJsTDJKcGJnb3ZhRzl0WlM5dFpTOHVZMkZ5WjI4dlltbHU=";
+    assert!(Env::is_legacy(legacy));
+
+    let env = Env::deserialize(legacy).expect("failed to deserialize");
+    assert_eq!(env.path, vec!["/opt/node/bin", "/home/me/.cargo/bin"]);
+    assert_eq!(env.vars["FOO"], "bar=baz");
+    assert_eq!(env.vars["GREETING"], "grüße 👋");
+
+    // An env without vars used to fail to deserialize
+    let path_only = ".L3dZQUFITk9ZVkJ3V1FFUkFBQ0hkaEJqTDI5d2RDOXViMlJsTDJKcGJn";
+    let env = Env::deserialize(path_only).expect("failed to deserialize");
+    assert_eq!(env.path, vec!["/opt/node/bin"]);
+    assert!(env.vars.is_empty());
+  }
+
+  #[test]
+  fn deserialize_invalid() {
+    assert!(matches!(
+      Env::deserialize("v99:abc"),
+      Err(EnvError::UnsupportedVersion(version)) if version == "v99"
+    ));
+    assert!(Env::deserialize("").is_err());
+    assert!(Env::deserialize("garbage").is_err());
+    assert!(Env::deserialize("v2:not base64!").is_err());
+    assert!(Env::deserialize("v2:aGVsbG8").is_err());
+  }
+}
@@ -65,6 +65,7 @@ impl GitRepo {
       Some(&args),
       Some(dir.as_ref()),
       None,
+      None,
       |output| output,
     )?;
 
@@ -103,6 +104,7 @@ impl GitRepo {
       Some(args_with_dirs),
       Some(&self.git_dir),
       None,
+      None,
       parse_output,
     )?)
   }
@@ -151,6 +153,12 @@ impl GitRepo {
     self.run_git(None, &["rev-parse", "--short", "HEAD"], |output| output)
   }
 
+  /// The full hash of the checked out commit. Unlike [`GitRepo::get_head_ref`],
+  /// this doesn't depend on the size of the repo or the git config.
+  pub fn get_head_commit(&self) -> Result<String, GitError> {
+    self.run_git(None, &["rev-parse", "HEAD"], |output| output)
+  }
+
   pub fn update_to_ref(&self, progress: Progress, rref: &str) -> Result<(), GitError> {
     self.run_git(Some(progress), &["fetch", "--prune", "origin"], |_| ())?;
     self.force_checkout(rref)?;
@@ -0,0 +1,223 @@
+use std::{
+  collections::HashMap,
+  ffi::OsString,
+  fmt, fs,
+  os::unix::fs::PermissionsExt,
+  path::{Path, PathBuf},
+  process::Command,
+};
+
+use thiserror::Error;
+
+use crate::{
+  process::{run_command, ProcessError, Progress},
+  scripts::{ASDF_INSTALL_PATH, ASDF_INSTALL_TYPE, ASDF_INSTALL_VERSION},
+  versions::Version,
+};
+
+const QWER_HOOK: &str = "QWER_HOOK";
+const QWER_DEFAULT_PACKAGES: &str = "QWER_DEFAULT_PACKAGES";
+
+const DEFAULT_PACKAGES: &str = "default-packages";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HookKind {
+  PreInstall,
+  PostInstall,
+  PreUninstall,
+  PostUninstall,
+}
+
+impl HookKind {
+  pub fn file_name(&self) -> &'static str {
+    match self {
+      HookKind::PreInstall => "pre-install",
+      HookKind::PostInstall => "post-install",
+      HookKind::PreUninstall => "pre-uninstall",
+      HookKind::PostUninstall => "post-uninstall",
+    }
+  }
+}
+
+impl fmt::Display for HookKind {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}", self.file_name())
+  }
+}
+
+#[derive(Error, Debug)]
+pub enum HookError {
+  #[error("{kind} hook {path} is not executable")]
+  NotExecutable { kind: HookKind, path: String },
+
+  #[error("{kind} hook failed:\n{output}\nThe full log is at {log}")]
+  Failed {
+    kind: HookKind,
+    output: String,
+    log: String,
+  },
+
+  #[error("failed to run {kind} hook: {source}")]
+  Process {
+    kind: HookKind,
+    source: ProcessError,
+  },
+}
+
+/// Scripts the user placed in the hooks directory of a tool, run around installs
+/// and uninstalls of its versions. A `default-packages` file next to them is
+/// passed to every hook, so a `post-install` hook can install it with the fresh
+/// version.
+pub struct UserHooks {
+  dir: PathBuf,
+}
+
+impl UserHooks {
+  pub fn new<P: AsRef<Path>>(dir: P) -> Self {
+    Self {
+      dir: dir.as_ref().to_path_buf(),
+    }
+  }
+
+  pub fn hook_path(&self, kind: HookKind) -> PathBuf {
+    self.dir.join(kind.file_name())
+  }
+
+  pub fn has_hook(&self, kind: HookKind) -> bool {
+    self.hook_path(kind).is_file()
+  }
+
+  /// Run the `kind` hook, if there is one. `env` is the whole environment of the
+  /// hook, nothing is inherited from qwer itself. It should activate the version
+  /// if it's installed. The output is appended to `log`.
+  pub fn run(
+    &self,
+    progress: Progress,
+    kind: HookKind,
+    version: &Version,
+    install_path: &Path,
+    env: &HashMap<OsString, OsString>,
+    log: &Path,
+  ) -> Result<(), HookError> {
+    let hook_path = self.hook_path(kind);
+    if !hook_path.is_file() {
+      return Ok(());
+    }
+
+    let executable = fs::metadata(&hook_path)
+      .map(|meta| meta.permissions().mode() & 0o111 != 0)
+      .unwrap_or(false);
+    if !executable {
+      return Err(HookError::NotExecutable {
+        kind,
+        path: hook_path.to_string_lossy().to_string(),
+      });
+    }
+
+    let install_path = install_path.to_string_lossy();
+    let default_packages = self.dir.join(DEFAULT_PACKAGES);
+    let default_packages = default_packages.to_string_lossy();
+
+    let mut hook_env = vec![
+      (QWER_HOOK, kind.file_name()),
+      (ASDF_INSTALL_TYPE, version.install_type()),
+      (ASDF_INSTALL_VERSION, version.version_str()),
+      (ASDF_INSTALL_PATH, &install_path),
+    ];
+
+    if self.dir.join(DEFAULT_PACKAGES).is_file() {
+      hook_env.push((QWER_DEFAULT_PACKAGES, &default_packages));
+    }
+
+    let mut cmd = Command::new(&hook_path);
+    cmd.env_clear().envs(env).envs(hook_env.iter().copied());
+
+    // Only the hook's own vars are logged, the rest is the env of the version
+    match run_command(Some(progress), cmd, Some(&hook_env), Some(log), |_| ()) {
+      Ok(()) => Ok(()),
+      Err(ProcessError::Failed(output)) => Err(HookError::Failed {
+        kind,
+        output,
+        log: log.to_string_lossy().to_string(),
+      }),
+      Err(source) => Err(HookError::Process { kind, source }),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use indicatif::ProgressBar;
+
+  use super::*;
+
+  fn write_hook(dir: &Path, kind: HookKind, script: &str) {
+    let path = dir.join(kind.file_name());
+    fs::write(&path, format!("#!/bin/sh\n{script}\n")).expect("failed to write hook");
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).expect("failed to chmod hook");
+  }
+
+  #[test]
+  fn run_hooks() {
+    let workdir = tempfile::tempdir().expect("failed to create temp dir");
+    let dir = workdir.as_ref();
+    let (log, out) = (dir.join("hook.log"), dir.join("out"));
+
+    fs::write(dir.join(DEFAULT_PACKAGES), "typescript\n").expect("failed to write file");
+    write_hook(
+      dir,
+      HookKind::PostInstall,
+      &format!(
+        "echo \"$QWER_HOOK $ASDF_INSTALL_VERSION $FOO ${{QWER_TEST_LEAK:-unset}} $(cat \"$QWER_DEFAULT_PACKAGES\")\" > {}",
+        out.to_string_lossy()
+      ),
+    );
+    write_hook(dir, HookKind::PreUninstall, "echo nope; exit 1");
+
+    let hooks = UserHooks::new(dir);
+    let bar = ProgressBar::hidden();
+    let version = Version::parse("1.2.3");
+    // Nothing is inherited, so vars of qwer itself don't leak into hooks
+    std::env::set_var("QWER_TEST_LEAK", "leaked");
+    let env = HashMap::from([
+      (OsString::from("FOO"), OsString::from("bar")),
+      (
+        OsString::from("PATH"),
+        std::env::var_os("PATH").unwrap_or_default(),
+      ),
+    ]);
+
+    // Missing hooks are skipped
+    assert!(!hooks.has_hook(HookKind::PreInstall));
+    hooks
+      .run((&bar, ""), HookKind::PreInstall, &version, dir, &env, &log)
+      .expect("missing hook failed");
+
+    hooks
+      .run((&bar, ""), HookKind::PostInstall, &version, dir, &env, &log)
+      .expect("hook failed");
+    assert_eq!(
+      fs::read_to_string(&out).unwrap(),
+      "post-install 1.2.3 bar unset typescript\n"
+    );
+
+    let err = hooks
+      .run(
+        (&bar, ""),
+        HookKind::PreUninstall,
+        &version,
+        dir,
+        &env,
+        &log,
+      )
+      .unwrap_err();
+    assert!(matches!(
+      err,
+      HookError::Failed {
+        kind: HookKind::PreUninstall,
+        ..
+      }
+    ));
+    assert!(fs::read_to_string(&log).unwrap().contains("nope"));
+  }
+}
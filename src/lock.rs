@@ -0,0 +1,183 @@
+use std::{
+  collections::BTreeMap,
+  fs, io,
+  os::unix::{ffi::OsStrExt, fs::PermissionsExt},
+  path::{Path, PathBuf},
+};
+
+use log::trace;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+pub const LOCKFILE_SUFFIX: &str = ".lock";
+
+#[derive(Error, Debug)]
+pub enum LockError {
+  #[error("io error while reading or writing lockfile: {0}")]
+  Io(#[from] io::Error),
+
+  #[error("failed to parse lockfile: {0}")]
+  Parse(#[from] toml::de::Error),
+
+  #[error("failed to serialize lockfile: {0}")]
+  Serialize(#[from] toml::ser::Error),
+}
+
+/// A single locked tool. The version is always fully resolved, so `latest`
+/// or similar will never appear here.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LockEntry {
+  pub version: String,
+  pub plugin_url: String,
+  pub plugin_ref: String,
+
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub checksum: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Lockfile {
+  #[serde(default)]
+  pub tools: BTreeMap<String, LockEntry>,
+}
+
+impl Lockfile {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// The lockfile belonging to a versions file, e.g. `.tool-versions.lock`
+  /// for `.tool-versions`.
+  pub fn path_for<P: AsRef<Path>>(versions_file: P) -> PathBuf {
+    let mut path = versions_file.as_ref().as_os_str().to_owned();
+    path.push(LOCKFILE_SUFFIX);
+    PathBuf::from(path)
+  }
+
+  pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, LockError> {
+    trace!("Loading lockfile from {:?}", path.as_ref());
+    let contents = fs::read_to_string(path)?;
+    Ok(toml::from_str(&contents)?)
+  }
+
+  pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), LockError> {
+    trace!("Saving lockfile to {:?}", path.as_ref());
+    let contents = toml::to_string(self)?;
+    fs::write(path, contents)?;
+    Ok(())
+  }
+}
+
+/// Hash the contents of a directory, including file names, symlink targets and
+/// file modes. Entries are visited in sorted order so the result is stable, and
+/// every field is prefixed with its length so different trees never hash the
+/// same bytes.
+pub fn checksum_dir<P: AsRef<Path>>(dir: P) -> Result<String, io::Error> {
+  let mut hasher = Sha256::new();
+  hash_dir(dir.as_ref(), dir.as_ref(), &mut hasher)?;
+  Ok(
+    hasher
+      .finalize()
+      .iter()
+      .map(|byte| format!("{byte:02x}"))
+      .collect(),
+  )
+}
+
+fn hash_dir(root: &Path, dir: &Path, hasher: &mut Sha256) -> Result<(), io::Error> {
+  let mut entries = fs::read_dir(dir)?.collect::<Result<Vec<_>, _>>()?;
+  entries.sort_by_key(|entry| entry.file_name());
+
+  for entry in entries {
+    let path = entry.path();
+    let relative = path.strip_prefix(root).unwrap_or(&path);
+
+    let metadata = fs::symlink_metadata(&path)?;
+    if metadata.is_symlink() {
+      hasher.update(b"l");
+      hash_field(hasher, relative.as_os_str().as_bytes());
+      hash_field(hasher, fs::read_link(&path)?.as_os_str().as_bytes());
+    } else if metadata.is_dir() {
+      hasher.update(b"d");
+      hash_field(hasher, relative.as_os_str().as_bytes());
+      hash_dir(root, &path, hasher)?;
+    } else {
+      hasher.update(b"f");
+      hash_field(hasher, relative.as_os_str().as_bytes());
+      hasher.update(metadata.permissions().mode().to_le_bytes());
+      hash_field(hasher, &fs::read(&path)?);
+    }
+  }
+
+  Ok(())
+}
+
+fn hash_field(hasher: &mut Sha256, bytes: &[u8]) {
+  hasher.update((bytes.len() as u64).to_le_bytes());
+  hasher.update(bytes);
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn roundtrip() {
+    let workdir = tempfile::tempdir().expect("failed to create temp dir");
+    let path = workdir.as_ref().join("lock");
+
+    let mut lockfile = Lockfile::new();
+    lockfile.tools.insert(
+      "foo".to_owned(),
+      LockEntry {
+        version: "1.2.3".to_owned(),
+        plugin_url: "https://example.com/foo.git".to_owned(),
+        plugin_ref: "abc123".to_owned(),
+        checksum: None,
+      },
+    );
+
+    lockfile.save(&path).expect("failed to save lockfile");
+    let loaded = Lockfile::load(&path).expect("failed to load lockfile");
+    assert_eq!(lockfile, loaded);
+  }
+
+  #[test]
+  fn path_for() {
+    assert_eq!(
+      Lockfile::path_for("/foo/.tool-versions"),
+      PathBuf::from("/foo/.tool-versions.lock")
+    );
+  }
+
+  #[test]
+  fn checksum_changes() {
+    let workdir = tempfile::tempdir().expect("failed to create temp dir");
+    fs::create_dir_all(workdir.as_ref().join("bin")).expect("failed to create dirs");
+    fs::write(workdir.as_ref().join("bin/foo"), "foo").expect("failed to write file");
+
+    let first = checksum_dir(&workdir).expect("failed to checksum");
+    assert_eq!(first, checksum_dir(&workdir).expect("failed to checksum"));
+
+    fs::write(workdir.as_ref().join("bin/foo"), "bar").expect("failed to write file");
+    assert_ne!(first, checksum_dir(&workdir).expect("failed to checksum"));
+  }
+
+  #[test]
+  fn checksum_separates_entries() {
+    let (link, dir) = (
+      tempfile::tempdir().expect("failed to create temp dir"),
+      tempfile::tempdir().expect("failed to create temp dir"),
+    );
+
+    // Without separators, a link `a` to `b` and a directory `ab` hash the same bytes
+    std::os::unix::fs::symlink("b", link.as_ref().join("a")).expect("failed to create link");
+    fs::create_dir(dir.as_ref().join("ab")).expect("failed to create dir");
+
+    assert_ne!(
+      checksum_dir(&link).expect("failed to checksum"),
+      checksum_dir(&dir).expect("failed to checksum")
+    );
+  }
+}
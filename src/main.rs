@@ -1,4 +1,4 @@
-use std::{io::Write, path::Path};
+use std::{io::Write, path::Path, time::Duration};
 
 use anyhow::{bail, Context, Result};
 use clap::{Parser, Subcommand};
@@ -9,17 +9,25 @@ use log::{error, trace};
 use crate::{
   dirs::{get_dir, BIN_DIR},
   shell::Shell,
+  versions::Bump,
 };
 
+mod cache;
 mod cmds;
 mod dirs;
+mod dotenv;
 mod env;
 mod git;
+mod hooks;
+mod lock;
 mod plugins;
 mod pretty;
 mod process;
+mod project;
+mod projects;
 mod scripts;
 mod shell;
+mod trust;
 mod versions;
 
 #[derive(Debug, Parser)]
@@ -40,6 +48,9 @@ enum Commands {
 
   #[clap(hide = true)]
   Export {
+    #[clap(long)]
+    pure: bool,
+
     #[clap(subcommand)]
     shell: ShellOptions,
   },
@@ -63,6 +74,17 @@ enum Commands {
 
     #[clap(long, short)]
     keep_download: bool,
+
+    #[clap(long, conflicts_with = "name")]
+    frozen: bool,
+
+    #[clap(long, short, conflicts_with = "name")]
+    jobs: Option<usize>,
+  },
+
+  Lock {
+    #[clap(long)]
+    checksums: bool,
   },
 
   Uninstall {
@@ -71,7 +93,104 @@ enum Commands {
   },
 
   Current {
+    name: Option<String>,
+
+    #[clap(long)]
+    json: bool,
+  },
+
+  Check {
+    #[clap(long)]
+    json: bool,
+  },
+
+  Trust {
+    path: Option<String>,
+  },
+
+  Untrust {
+    path: Option<String>,
+  },
+
+  Env {
+    dir: Option<String>,
+  },
+
+  Explain {
+    dir: Option<String>,
+  },
+
+  Logs {
     name: String,
+    version: Option<String>,
+  },
+
+  Cache {
+    #[clap(subcommand)]
+    command: CacheCommand,
+  },
+
+  Outdated {
+    #[clap(long)]
+    global: bool,
+
+    #[clap(long)]
+    json: bool,
+
+    #[clap(long, arg_enum)]
+    fail_on: Option<Bump>,
+  },
+
+  Du,
+
+  Projects {
+    #[clap(long)]
+    json: bool,
+  },
+
+  Prune {
+    #[clap(long)]
+    dry_run: bool,
+
+    #[clap(long, default_value = "0")]
+    keep_latest: usize,
+
+    #[clap(long, default_value = "30days", parse(try_from_str = humantime::parse_duration))]
+    recent: Duration,
+  },
+
+  Upgrade {
+    tools: Vec<String>,
+
+    #[clap(long, conflicts_with_all = &["minor", "patch"])]
+    major: bool,
+
+    #[clap(long, conflicts_with = "patch")]
+    minor: bool,
+
+    #[clap(long)]
+    patch: bool,
+
+    #[clap(long)]
+    global: bool,
+
+    #[clap(long)]
+    dry_run: bool,
+  },
+
+  #[clap(trailing_var_arg = true)]
+  Exec {
+    #[clap(long)]
+    cd: Option<String>,
+
+    #[clap(long)]
+    pure: bool,
+
+    #[clap(long, requires = "pure")]
+    clear_env: bool,
+
+    #[clap(required = true, allow_hyphen_values = true)]
+    command: Vec<String>,
   },
 
   Where {
@@ -176,6 +295,15 @@ enum PluginUpdateCommand {
   All,
 }
 
+#[derive(Debug, Subcommand)]
+enum CacheCommand {
+  Ls,
+
+  Clear { name: Option<String> },
+
+  Prune,
+}
+
 #[derive(Debug, Subcommand)]
 enum ListCommand {
   All {
@@ -263,11 +391,16 @@ fn main() -> Result<()> {
 
       Ok(())
     }
-    Commands::Export { shell } => {
+    Commands::Export { pure, shell } => {
       trace!("Exporting {} env", shell.name());
       assert_running_qwer(is_asdf)?;
 
-      let state = cmds::env::update_env()?;
+      let state = if pure {
+        cmds::env::pure_env()?
+      } else {
+        cmds::env::update_env()?
+      };
+
       let set_env = shell.get().apply(&state);
 
       trace!("Resolved env export:\n{set_env}");
@@ -317,16 +450,65 @@ fn main() -> Result<()> {
       version,
       concurrency,
       keep_download,
+      frozen,
+      jobs,
     } => match (name, version) {
-      (None, None) => cmds::install::install_all(concurrency, keep_download),
+      (None, None) => cmds::install::install_all(concurrency, keep_download, frozen, jobs),
       (Some(name), None) => cmds::install::install_one(name, concurrency, keep_download),
       (Some(name), Some(version)) => {
         cmds::install::install_one_version(name, version, concurrency, keep_download)
       }
       _ => unreachable!(),
     },
+    Commands::Lock { checksums } => cmds::lock::lock(checksums),
     Commands::Uninstall { name, version } => cmds::install::uninstall(name, version),
-    Commands::Current { name } => cmds::env::current(name),
+    Commands::Current { name, json } => cmds::env::current(name, json),
+    Commands::Check { json } => cmds::check::check(json),
+    Commands::Trust { path } => cmds::trust::trust(path),
+    Commands::Untrust { path } => cmds::trust::untrust(path),
+    Commands::Env { dir } => cmds::env::env(dir),
+    Commands::Explain { dir } => cmds::explain::explain(dir),
+    Commands::Logs { name, version } => cmds::logs::logs(name, version),
+    Commands::Outdated {
+      global,
+      json,
+      fail_on,
+    } => cmds::outdated::outdated(global, json, fail_on),
+    Commands::Du => cmds::du::du(),
+    Commands::Projects { json } => cmds::projects::projects(json),
+    Commands::Prune {
+      dry_run,
+      keep_latest,
+      recent,
+    } => cmds::prune::prune(dry_run, keep_latest, recent),
+    Commands::Upgrade {
+      tools,
+      major: _,
+      minor,
+      patch,
+      global,
+      dry_run,
+    } => {
+      // Without a flag, upgrade to the latest version
+      let level = match (minor, patch) {
+        (_, true) => Bump::Patch,
+        (true, _) => Bump::Minor,
+        _ => Bump::Major,
+      };
+
+      cmds::upgrade::upgrade(tools, level, global, dry_run)
+    }
+    Commands::Cache { command } => match command {
+      CacheCommand::Ls => cmds::cache::ls(),
+      CacheCommand::Clear { name } => cmds::cache::clear(name),
+      CacheCommand::Prune => cmds::cache::prune(),
+    },
+    Commands::Exec {
+      cd,
+      pure,
+      clear_env,
+      command,
+    } => cmds::exec::exec(cd, pure, clear_env, command),
     Commands::Where { name, version } => cmds::env::wwhere(name, version),
     Commands::Latest { name, filter } => cmds::list::latest(name, filter),
     Commands::List {
@@ -357,6 +539,7 @@ fn main() -> Result<()> {
 
   if let Err(err) = result {
     error!("{}", err);
+    std::process::exit(1);
   }
 
   Ok(())
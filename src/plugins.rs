@@ -213,6 +213,18 @@ pub fn list_all(force_refresh: bool) -> Result<Vec<PluginListEntry>> {
     .collect::<Result<Vec<_>>>()
 }
 
+/// Check whether a plugin is available from the plugin registry.
+pub fn in_registry(name: &str) -> Result<bool> {
+  update_registry(DEFAULT_PLUGIN_REGISTRY_URL, DEFAULT_PLUGIN_REGISTRY, false)?;
+
+  let registry_dir = get_dir(REGISTRIES_DIR)?.join(DEFAULT_PLUGIN_REGISTRY);
+  match parse_short_repo_url(registry_dir, name) {
+    Ok(_) => Ok(true),
+    Err(RegistryError::NotFound(_)) => Ok(false),
+    Err(err) => Err(err.into()),
+  }
+}
+
 /// Retrieve the repository url from a directory containing plugin references.
 /// See [the asdf plugin repository](https://github.com/asdf-vm/asdf-plugins/tree/master/plugins)
 /// for the expected file format and contents.
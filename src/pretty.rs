@@ -12,6 +12,24 @@ pub fn version(name: &str) -> StyledObject<&str> {
   style(name).bold().cyan()
 }
 
+/// Format a size in bytes, e.g. `1.5 GiB`.
+pub fn size(bytes: u64) -> String {
+  const UNITS: &[&str] = &["B", "KiB", "MiB", "GiB", "TiB"];
+
+  let mut size = bytes as f64;
+  let mut unit = 0;
+  while size >= 1024.0 && unit < UNITS.len() - 1 {
+    size /= 1024.0;
+    unit += 1;
+  }
+
+  if unit == 0 {
+    format!("{bytes} B")
+  } else {
+    format!("{size:.1} {}", UNITS[unit])
+  }
+}
+
 pub fn _registry(name: &str) -> StyledObject<&str> {
   style(name).bold().yellow()
 }
@@ -1,12 +1,12 @@
 use std::{
   collections::VecDeque,
   ffi::OsStr,
-  fs::File,
-  io::{self, Read},
-  os::unix::prelude::{AsRawFd, FromRawFd},
+  fs::{self, File, OpenOptions},
+  io::{self, Read, Write},
+  os::unix::prelude::{FromRawFd, IntoRawFd},
   path::Path,
   process::{Child, Command, ExitStatus},
-  time::Duration,
+  time::{Duration, SystemTime},
 };
 
 use console::style;
@@ -65,12 +65,15 @@ pub fn auto_bar() -> ProgressBar {
   bar
 }
 
+/// Run a command and parse its output. If `log` is set, the full output is
+/// appended to that file, together with the env that was passed and timestamps.
 pub fn run<Cmd, T>(
   show_progress: Option<Progress>,
   command: Cmd,
   args: Option<&[&str]>,
   dir: Option<&Path>,
   env: Option<&[(&str, &str)]>,
+  log: Option<&Path>,
   parse_output: impl FnOnce(String) -> T + 'static,
 ) -> Result<T, ProcessError>
 where
@@ -94,20 +97,54 @@ where
     }
   }
 
+  run_command(show_progress, cmd, env, log, parse_output)
+}
+
+/// Same as [`run`], but for a command that was already set up. Only `logged_env`
+/// is written to the log, so commands that get a whole env don't dump all of it.
+pub fn run_command<T>(
+  show_progress: Option<Progress>,
+  mut cmd: Command,
+  logged_env: Option<&[(&str, &str)]>,
+  log: Option<&Path>,
+  parse_output: impl FnOnce(String) -> T + 'static,
+) -> Result<T, ProcessError>
+where
+  T: 'static,
+{
+  let mut log = match log {
+    Some(path) => Some(ScriptLog::open(path, &cmd, logged_env)?),
+    None => None,
+  };
+
   let (status, output_str, all_output) = if let Some((bar, message)) = show_progress {
     bar.set_message(message.to_string());
-    let (status, output_str, all_output) = read_process(cmd, bar, message)?;
+    let (status, output_str, all_output) = read_process(cmd, bar, message, log.as_mut())?;
     bar.set_message(message.to_string());
     (status, output_str, all_output)
   } else {
     let output = cmd.output()?;
     let output_str = String::from_utf8_lossy(&output.stdout).to_string();
     let stderr_str = String::from_utf8_lossy(&output.stderr).to_string();
+
+    if let Some(log) = log.as_mut() {
+      output_str
+        .lines()
+        .for_each(|line| log.line(Stream::Stdout, line));
+      stderr_str
+        .lines()
+        .for_each(|line| log.line(Stream::Stderr, line));
+    }
+
     (output.status, output_str, stderr_str)
   };
 
   trace!("Got process output:\n{output_str}");
 
+  if let Some(log) = log.as_mut() {
+    log.finish(status);
+  }
+
   if !status.success() {
     return Err(ProcessError::Failed(all_output));
   }
@@ -119,6 +156,7 @@ fn read_process(
   cmd: Command,
   bar: &ProgressBar,
   message: &str,
+  mut log: Option<&mut ScriptLog>,
 ) -> Result<(ExitStatus, String, String), io::Error> {
   let mut lines = Vec::new();
   let mut stdout_lines = Vec::new();
@@ -132,10 +170,20 @@ fn read_process(
         return Ok((status, stdout, all_output));
       }
       Ok(Out::Stdout(line)) => {
+        if let Some(log) = log.as_mut() {
+          log.line(Stream::Stdout, &line);
+        }
+
         stdout_lines.push(line);
         continue;
       }
-      Ok(Out::Stderr(line)) => line,
+      Ok(Out::Stderr(line)) => {
+        if let Some(log) = log.as_mut() {
+          log.line(Stream::Stderr, &line);
+        }
+
+        line
+      }
       Err(err) => return Err(err),
     };
 
@@ -163,6 +211,52 @@ fn read_process(
   unreachable!()
 }
 
+/// Appends the output of a command to a log file. Failing to write the log
+/// shouldn't fail the command, so write errors are ignored after opening it.
+struct ScriptLog {
+  file: File,
+}
+
+impl ScriptLog {
+  fn open(path: &Path, cmd: &Command, env: Option<&[(&str, &str)]>) -> Result<Self, io::Error> {
+    if let Some(parent) = path.parent() {
+      fs::create_dir_all(parent)?;
+    }
+
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    let mut command = cmd.get_program().to_string_lossy().to_string();
+    for arg in cmd.get_args() {
+      command.push(' ');
+      command.push_str(&arg.to_string_lossy());
+    }
+
+    writeln!(file, "# {} Running {command}", timestamp())?;
+
+    for (key, val) in env.unwrap_or_default() {
+      writeln!(file, "# env {key}={val}")?;
+    }
+
+    Ok(Self { file })
+  }
+
+  fn line(&mut self, stream: Stream, line: &str) {
+    let stream = match stream {
+      Stream::Stdout => "out",
+      Stream::Stderr => "err",
+    };
+
+    let _ = writeln!(self.file, "{} {stream} | {line}", timestamp());
+  }
+
+  fn finish(&mut self, status: ExitStatus) {
+    let _ = writeln!(self.file, "# {} Finished with {status}\n", timestamp());
+  }
+}
+
+fn timestamp() -> humantime::Rfc3339Timestamp {
+  humantime::format_rfc3339_seconds(SystemTime::now())
+}
+
 #[derive(Clone, Debug)]
 enum Out {
   Stdout(String),
@@ -197,8 +291,10 @@ impl ProcessReader {
     let (stdout_write, mut stdout_read) = mio::unix::pipe::new()?;
     let (stderr_write, mut stderr_read) = mio::unix::pipe::new()?;
 
-    let stdout_file = unsafe { File::from_raw_fd(stdout_write.as_raw_fd()) };
-    let stderr_file = unsafe { File::from_raw_fd(stderr_write.as_raw_fd()) };
+    // Hand ownership of the write ends to the child, so they are closed exactly
+    // once. Other threads might reuse the fds as soon as they are closed.
+    let stdout_file = unsafe { File::from_raw_fd(stdout_write.into_raw_fd()) };
+    let stderr_file = unsafe { File::from_raw_fd(stderr_write.into_raw_fd()) };
 
     let child = cmd.stdout(stdout_file).stderr(stderr_file).spawn()?;
 
@@ -231,6 +327,36 @@ impl ProcessReader {
       done: false,
     })
   }
+
+  fn drain(&mut self) -> Result<(), io::Error> {
+    read_pipe(
+      &mut self.stdout_read,
+      &mut self.stdout_buf,
+      &mut self.output_buf,
+      Stream::Stdout,
+    )?;
+    read_pipe(
+      &mut self.stderr_read,
+      &mut self.stderr_buf,
+      &mut self.output_buf,
+      Stream::Stderr,
+    )?;
+
+    // Output without a trailing newline
+    if !self.stdout_buf.is_empty() {
+      let line = String::from_utf8_lossy(&self.stdout_buf).to_string();
+      self.output_buf.push_back(Out::Stdout(line));
+      self.stdout_buf.clear();
+    }
+
+    if !self.stderr_buf.is_empty() {
+      let line = String::from_utf8_lossy(&self.stderr_buf).to_string();
+      self.output_buf.push_back(Out::Stderr(line));
+      self.stderr_buf.clear();
+    }
+
+    Ok(())
+  }
 }
 
 fn read_pipe(
@@ -305,6 +431,11 @@ impl Iterator for ProcessReader {
       match self.child.try_wait() {
         Ok(None) => {}
         Ok(Some(status)) => {
+          // The child might have exited before we read all of its output
+          if let Err(err) = self.drain() {
+            return Some(Err(err));
+          }
+
           self.status = Some(status);
           continue;
         }
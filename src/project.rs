@@ -0,0 +1,222 @@
+use std::{
+  collections::BTreeMap,
+  fs, io,
+  path::{Path, PathBuf},
+};
+
+use log::trace;
+use serde::Deserialize;
+use thiserror::Error;
+use toml::Spanned;
+
+use crate::{
+  env::Env,
+  versions::{Versions, VersionsError, WalkOptions},
+};
+
+const PROJECT_ROOT_TEMPLATE: &str = "{{project_root}}";
+
+#[derive(Error, Debug)]
+pub enum ProjectError {
+  #[error("failed to parse project config `{0}`: {1}")]
+  Parse(PathBuf, toml::de::Error),
+
+  #[error("error while looking for project config: {0}")]
+  Versions(#[from] VersionsError),
+
+  #[error("io error while reading project config: {0}")]
+  Io(#[from] io::Error),
+}
+
+/// A project config file, e.g.:
+///
+/// ```toml
+/// [env]
+/// path = ["./node_modules/.bin", "./bin"]
+///
+/// [env.vars]
+/// DATABASE_URL = "postgres://localhost/app"
+/// CONFIG_DIR = "{{project_root}}/config"
+/// ```
+#[derive(Debug, Default, Deserialize)]
+pub struct ProjectConfig {
+  #[serde(default)]
+  pub env: ProjectEnv,
+
+  /// The line each var was defined on, if the config was loaded from a file.
+  #[serde(skip)]
+  pub lines: BTreeMap<String, usize>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ProjectEnv {
+  /// Path entries, relative entries are resolved against the project root.
+  #[serde(default)]
+  pub path: Vec<String>,
+
+  #[serde(default)]
+  pub vars: BTreeMap<String, Spanned<String>>,
+}
+
+impl ProjectConfig {
+  pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, ProjectError> {
+    trace!("Loading project config from {:?}", path.as_ref());
+    let contents = fs::read_to_string(&path)?;
+    let mut config: Self = toml::from_str(&contents)
+      .map_err(|err| ProjectError::Parse(path.as_ref().to_owned(), err))?;
+
+    config.lines = config
+      .env
+      .vars
+      .iter()
+      .map(|(key, val)| {
+        (
+          key.clone(),
+          contents[..val.start()].matches('\n').count() + 1,
+        )
+      })
+      .collect();
+
+    Ok(config)
+  }
+
+  /// Build the env for this config, with `root` being the directory the
+  /// config file is in. `{{project_root}}` is replaced in all values.
+  pub fn to_env<P: AsRef<Path>>(&self, root: P) -> Env {
+    let root = root.as_ref();
+    let root_str = root.to_string_lossy();
+    let mut env = Env::default();
+
+    for entry in &self.env.path {
+      let entry = entry.replace(PROJECT_ROOT_TEMPLATE, &root_str);
+      let entry = Path::new(&entry);
+      let entry = if entry.is_absolute() {
+        entry.to_owned()
+      } else {
+        root.join(entry.strip_prefix("./").unwrap_or(entry))
+      };
+
+      env.add_path(entry.to_string_lossy());
+    }
+
+    for (key, val) in &self.env.vars {
+      env.vars.insert(
+        key.clone(),
+        val.get_ref().replace(PROJECT_ROOT_TEMPLATE, &root_str),
+      );
+    }
+
+    env
+  }
+}
+
+/// A project config that was found, with its env and the line of every var.
+pub type FoundProjectEnv = (PathBuf, Env, BTreeMap<String, usize>);
+
+/// Find all project configs by walking up from `workdir`, skipping files rejected
+/// by `filter`, and build the env of each. Closer configs come first, since they take precedence over ones further up.
+pub fn find_project_envs<P: AsRef<Path>>(
+  workdir: P,
+  filename: &str,
+  options: &WalkOptions,
+  filter: impl Fn(&Path) -> bool,
+) -> Result<Vec<FoundProjectEnv>, ProjectError> {
+  let mut result = Vec::new();
+
+  for path in Versions::find_all_files(workdir, filename, options)? {
+    if !filter(&path) {
+      continue;
+    }
+
+    let config = ProjectConfig::load(&path)?;
+    let root = path.parent().unwrap_or_else(|| Path::new("/"));
+    let env = config.to_env(root);
+    result.push((path, env, config.lines));
+  }
+
+  Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn templates_and_relative_paths() {
+    let config: ProjectConfig = toml::from_str(
+      r#"
+[env]
+path = ["./node_modules/.bin", "bin", "/opt/bin", "{{project_root}}/tools"]
+
+[env.vars]
+FOO = "bar"
+CONFIG = "{{project_root}}/config"
+"#,
+    )
+    .expect("failed to parse config");
+
+    let env = config.to_env("/work/app");
+    assert_eq!(
+      env.path,
+      vec![
+        "/work/app/node_modules/.bin",
+        "/work/app/bin",
+        "/opt/bin",
+        "/work/app/tools"
+      ]
+    );
+    assert_eq!(env.vars["FOO"], "bar");
+    assert_eq!(env.vars["CONFIG"], "/work/app/config");
+  }
+
+  #[test]
+  fn closer_configs_take_precedence() {
+    let workdir = tempfile::tempdir().expect("failed to create temp dir");
+    let subdir = workdir.as_ref().join("sub");
+    fs::create_dir_all(&subdir).expect("failed to create dirs");
+    fs::write(
+      workdir.as_ref().join("p.toml"),
+      "[env]\npath = [\"bin\"]\n[env.vars]\nFOO = \"outer\"\nBAR = \"outer\"",
+    )
+    .expect("failed to write config");
+    fs::write(
+      subdir.join("p.toml"),
+      "[env]\npath = [\"bin\"]\n[env.vars]\nFOO = \"inner\"",
+    )
+    .expect("failed to write config");
+
+    let envs = find_project_envs(&subdir, "p.toml", &WalkOptions::default(), |_| true)
+      .expect("failed to find project envs");
+    assert_eq!(envs[0].0, subdir.join("p.toml"));
+
+    let mut env = Env::default();
+    for (_, project_env, _) in envs {
+      env.merge_lower(project_env);
+    }
+
+    assert_eq!(
+      env.path,
+      vec![
+        subdir.join("bin").to_string_lossy(),
+        workdir.as_ref().join("bin").to_string_lossy()
+      ]
+    );
+    assert_eq!(env.vars["FOO"], "inner");
+    assert_eq!(env.vars["BAR"], "outer");
+  }
+
+  #[test]
+  fn var_lines() {
+    let workdir = tempfile::tempdir().expect("failed to create temp dir");
+    let path = workdir.as_ref().join("p.toml");
+    fs::write(
+      &path,
+      "# Project\n[env]\npath = [\"bin\"]\n\n[env.vars]\nFOO = \"foo\"\nBAR = \"\"\"\nbar\n\"\"\"\n",
+    )
+    .expect("failed to write config");
+
+    let config = ProjectConfig::load(&path).expect("failed to load config");
+    assert_eq!(config.lines["FOO"], 6);
+    assert_eq!(config.lines["BAR"], 7);
+  }
+}
@@ -0,0 +1,128 @@
+use std::{
+  collections::BTreeMap,
+  fs, io,
+  path::{Path, PathBuf},
+  time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::dirs::TOOL_VERSIONS;
+
+#[derive(Error, Debug)]
+pub enum ProjectRegistryError {
+  #[error("io error while reading or writing project registry: {0}")]
+  Io(#[from] io::Error),
+
+  #[error("failed to parse project registry: {0}")]
+  Parse(#[from] toml::de::Error),
+
+  #[error("failed to serialize project registry: {0}")]
+  Serialize(#[from] toml::ser::Error),
+}
+
+/// Directories with a versions file that qwer resolved at some point, keyed by
+/// their canonical path, together with when they were last seen in seconds since
+/// the unix epoch.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ProjectRegistry {
+  #[serde(default)]
+  projects: BTreeMap<String, u64>,
+}
+
+impl ProjectRegistry {
+  pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, ProjectRegistryError> {
+    if !path.as_ref().is_file() {
+      return Ok(Self::default());
+    }
+
+    let contents = fs::read_to_string(path)?;
+    Ok(toml::from_str(&contents)?)
+  }
+
+  /// Save the registry. Every shell hook can write it, so it's written to a
+  /// temporary file first and then moved in place, to never leave a half
+  /// written registry behind.
+  pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), ProjectRegistryError> {
+    let path = path.as_ref();
+    let tmp_path = path.with_extension(format!("{}.tmp", std::process::id()));
+    fs::write(&tmp_path, toml::to_string(self)?)?;
+    fs::rename(&tmp_path, path)?;
+    Ok(())
+  }
+
+  pub fn record<P: AsRef<Path>>(&mut self, dir: P) {
+    let key = fs::canonicalize(&dir)
+      .unwrap_or_else(|_| PathBuf::from(dir.as_ref()))
+      .to_string_lossy()
+      .to_string();
+
+    let now = SystemTime::now()
+      .duration_since(UNIX_EPOCH)
+      .map(|since| since.as_secs())
+      .unwrap_or_default();
+
+    self.projects.insert(key, now);
+  }
+
+  /// Drop all projects that don't have a versions file anymore, returning them.
+  pub fn remove_missing(&mut self) -> Vec<PathBuf> {
+    let missing = self
+      .dirs()
+      .filter(|dir| !dir.join(TOOL_VERSIONS).is_file())
+      .collect::<Vec<_>>();
+
+    for dir in &missing {
+      self.projects.remove(&*dir.to_string_lossy());
+    }
+
+    missing
+  }
+
+  pub fn dirs(&self) -> impl Iterator<Item = PathBuf> + '_ {
+    self.projects.keys().map(PathBuf::from)
+  }
+
+  /// When the project in `dir` was last seen, in seconds since the unix epoch.
+  pub fn last_seen<P: AsRef<Path>>(&self, dir: P) -> Option<u64> {
+    self.projects.get(&*dir.as_ref().to_string_lossy()).copied()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn record_and_remove_missing() {
+    let workdir = tempfile::tempdir().expect("failed to create temp dir");
+    let (kept, removed) = (
+      workdir.as_ref().join("kept"),
+      workdir.as_ref().join("removed"),
+    );
+    for dir in [&kept, &removed] {
+      fs::create_dir(dir).expect("failed to create dir");
+      fs::write(dir.join(TOOL_VERSIONS), "foo 1").expect("failed to write file");
+    }
+
+    let registry_path = workdir.as_ref().join("projects.toml");
+    let mut registry = ProjectRegistry::default();
+    registry.record(&kept);
+    registry.record(&removed);
+    registry
+      .save(&registry_path)
+      .expect("failed to save registry");
+
+    fs::remove_file(removed.join(TOOL_VERSIONS)).expect("failed to remove file");
+
+    let mut loaded = ProjectRegistry::load(&registry_path).expect("failed to load registry");
+    let (kept, removed) = (
+      kept.canonicalize().unwrap(),
+      removed.canonicalize().unwrap(),
+    );
+    assert_eq!(loaded.remove_missing(), vec![removed]);
+    assert_eq!(loaded.dirs().collect::<Vec<_>>(), vec![kept.clone()]);
+    assert!(loaded.last_seen(&kept).is_some());
+  }
+}
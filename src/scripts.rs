@@ -1,22 +1,27 @@
 use std::{
-  collections::{HashMap, HashSet},
+  collections::{BTreeMap, HashMap},
   fs,
-  os::unix::prelude::PermissionsExt,
+  hash::Hasher,
   path::{Path, PathBuf},
   process::Command,
+  time::SystemTime,
 };
 
 use anyhow::{bail, Result};
+use indicatif::ProgressBar;
 use lazy_static::lazy_static;
-use log::trace;
+use log::{info, trace, warn};
 use regex::Regex;
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
 use crate::{
+  cache::lock_active,
   env::{Env, IGNORED_ENV_VARS},
+  lock::checksum_dir,
   pretty,
   process::{auto_bar, run, ProcessError, Progress},
-  versions::Version,
+  versions::{compare_versions, Version},
 };
 
 lazy_static! {
@@ -24,12 +29,11 @@ lazy_static! {
     "-src|-dev|-latest|-stm|[-\\.]rc|-alpha|-beta|[-\\.]pre|-next|(a|b|c)[0-9]+|snapshot|master"
   )
   .unwrap();
-  static ref EXPORT_ECHO_RE: Regex = Regex::new("export ").unwrap();
 }
 
-const ASDF_INSTALL_TYPE: &str = "ASDF_INSTALL_TYPE";
-const ASDF_INSTALL_VERSION: &str = "ASDF_INSTALL_VERSION";
-const ASDF_INSTALL_PATH: &str = "ASDF_INSTALL_PATH";
+pub const ASDF_INSTALL_TYPE: &str = "ASDF_INSTALL_TYPE";
+pub const ASDF_INSTALL_VERSION: &str = "ASDF_INSTALL_VERSION";
+pub const ASDF_INSTALL_PATH: &str = "ASDF_INSTALL_PATH";
 const ASDF_DOWNLOAD_PATH: &str = "ASDF_DOWNLOAD_PATH";
 const ASDF_CONCURRENCY: &str = "ASDF_CONCURRENCY";
 const ASDF_PLUGIN_PATH: &str = "ASDF_PLUGIN_PATH";
@@ -37,6 +41,12 @@ const ASDF_PLUGIN_SOURCE_URL: &str = "ASDF_PLUGIN_SOURCE_URL";
 const ASDF_PLUGIN_PREV_REF: &str = "ASDF_PLUGIN_PREV_REF";
 const ASDF_PLUGIN_POST_REF: &str = "ASDF_PLUGIN_POST_REF";
 
+const EXEC_ENV_SEPARATOR: &str = "__QWER_EXEC_ENV__";
+
+/// Vars that are passed through to `exec-env`, since scripts commonly rely on them.
+/// Everything else is cleared to keep the result independent of the calling shell.
+const EXEC_ENV_PASSTHROUGH: &[&str] = &["HOME", "USER", "LOGNAME", "LANG", "TMPDIR"];
+
 #[derive(Error, Debug)]
 pub enum PluginScriptError {
   #[error("script `{0}` was not found")]
@@ -54,11 +64,40 @@ pub enum PluginScriptError {
   #[error("Version `{version}` for plugin `{plugin}` was already installed")]
   VersionAlreadyInstalled { version: String, plugin: String },
 
+  #[error("Version `{version}` for plugin `{plugin}` is being installed by pid {pid}")]
+  InstallInProgress {
+    version: String,
+    plugin: String,
+    pid: String,
+  },
+
   #[error("no versions were found")]
   NoVersionsFound,
 
   #[error("error while running script: {0}")]
   ProcessError(#[from] ProcessError),
+
+  #[error("process returned a non-zero exit code:\n{output}\nThe full log is at {log}")]
+  ScriptFailed { output: String, log: String },
+
+  #[error("exec-env script exited before it finished:\n{0}")]
+  ExecEnvFailed(String),
+}
+
+/// The env of an installed version, split by the script that produced it.
+#[derive(Debug, Clone, Default)]
+pub struct VersionEnv {
+  pub exec_env: Env,
+  pub bin_paths: Env,
+}
+
+impl VersionEnv {
+  /// Combine both parts, with `exec-env` path entries coming first.
+  pub fn combined(&self) -> Env {
+    let mut env = self.exec_env.clone();
+    env.merge(self.bin_paths.clone());
+    env
+  }
 }
 
 pub struct PluginScripts {
@@ -66,25 +105,33 @@ pub struct PluginScripts {
   plugin_dir: PathBuf,
   install_dir: PathBuf,
   download_dir: PathBuf,
+  exec_env_cache_dir: PathBuf,
+  log_dir: PathBuf,
   script_env_path: String,
 }
 
 impl PluginScripts {
-  pub fn new<Plugin, Install, Download>(
+  pub fn new<Plugin, Install, Download, ExecEnvCache, Logs>(
     name: &str,
     plugins: Plugin,
     installs: Install,
     downloads: Download,
+    exec_env_cache: ExecEnvCache,
+    logs: Logs,
     extra_path: &[&str],
   ) -> Result<Self>
   where
     Plugin: AsRef<Path>,
     Install: AsRef<Path>,
     Download: AsRef<Path>,
+    ExecEnvCache: AsRef<Path>,
+    Logs: AsRef<Path>,
   {
     let plugin_dir = plugins.as_ref().join(name);
     let install_dir = installs.as_ref().join(name);
     let download_dir = downloads.as_ref().join(name);
+    let exec_env_cache_dir = exec_env_cache.as_ref().join(name);
+    let log_dir = logs.as_ref().join(name);
     let name = name.to_owned();
 
     let mut script_env_path = extra_path
@@ -103,6 +150,8 @@ impl PluginScripts {
       plugin_dir,
       install_dir,
       download_dir,
+      exec_env_cache_dir,
+      log_dir,
       script_env_path,
     })
   }
@@ -133,10 +182,45 @@ impl PluginScripts {
       None,
       None,
       Some(&env),
+      None,
       parse_output,
     )?)
   }
 
+  /// Same as [`PluginScripts::run_script`], but the full output is also written to
+  /// the log of `version`. Errors point to the log, since the progress bar only
+  /// shows the last few lines.
+  fn run_script_logged<P: AsRef<Path>, T: 'static>(
+    &self,
+    show_progress: Option<Progress>,
+    script_path: P,
+    env: &[(&str, &str)],
+    version: &Version,
+    parse_output: impl FnOnce(String) -> T + 'static,
+  ) -> Result<T> {
+    log_script(script_path.as_ref());
+    let env = self.merge_env(env);
+    let log_path = self.log_path(version);
+
+    let result = run(
+      show_progress,
+      script_path.as_ref(),
+      None,
+      None,
+      Some(&env),
+      Some(&log_path),
+      parse_output,
+    );
+
+    match result {
+      Err(ProcessError::Failed(output)) => Err(PluginScriptError::ScriptFailed {
+        output,
+        log: log_path.to_string_lossy().to_string(),
+      })?,
+      result => Ok(result?),
+    }
+  }
+
   fn assert_script_exists<P: AsRef<Path>>(&self, script: P) -> Result<()> {
     if log::log_enabled!(log::Level::Trace) {
       trace!("Asserting script `{:?}` exists", script.as_ref());
@@ -179,7 +263,40 @@ impl PluginScripts {
   }
 
   pub fn version_installed(&self, version: &Version) -> bool {
-    self.install_dir.join(version.version_str()).is_dir()
+    install_complete(&self.install_dir, version.version_str())
+  }
+
+  /// When `version` was last activated by the hook or `qwer exec`. Versions that
+  /// were never activated count as used when they were installed.
+  pub fn last_used(&self, version: &Version) -> Option<SystemTime> {
+    let version_str = version.version_str();
+    fs::metadata(used_stamp_path(&self.install_dir, version_str))
+      .or_else(|_| fs::metadata(self.install_dir.join(version_str)))
+      .and_then(|meta| meta.modified())
+      .ok()
+  }
+
+  /// All completely installed versions, from oldest to newest.
+  pub fn installed_versions(&self) -> Result<Vec<String>> {
+    if !self.install_dir.is_dir() {
+      return Ok(vec![]);
+    }
+
+    let mut versions = fs::read_dir(&self.install_dir)?
+      .collect::<Result<Vec<_>, _>>()?
+      .into_iter()
+      .map(|entry| entry.file_name().to_string_lossy().to_string())
+      .filter(|version| !version.starts_with('.') && install_complete(&self.install_dir, version))
+      .collect::<Vec<_>>();
+
+    versions.sort_by(|a, b| compare_versions(a, b));
+    Ok(versions)
+  }
+
+  /// Check whether an install of `version` was started but never finished, e.g.
+  /// because qwer crashed or was interrupted.
+  pub fn version_incomplete(&self, version: &Version) -> bool {
+    install_marker_path(&self.install_dir, version.version_str()).exists()
   }
 
   pub fn find_version(&self, version: &str) -> Result<Version> {
@@ -219,7 +336,9 @@ impl PluginScripts {
     self.plugin_dir.join("bin/download").is_file()
   }
 
-  pub fn download(&self, version: &Version) -> Result<Option<()>> {
+  /// Run the download script, reporting progress on `bar`. The bar is left for the
+  /// caller to finish, so a whole install can share one.
+  pub fn download(&self, version: &Version, bar: &ProgressBar) -> Result<Option<()>> {
     if version == &Version::System {
       return Ok(None);
     }
@@ -233,18 +352,31 @@ impl PluginScripts {
     let version_str = version.version_str();
     let version_download_dir = self.download_dir.join(version_str);
     let version_install_dir = self.install_dir.join(version_str);
-    if version_install_dir.is_dir() {
+    if self.version_installed(version) {
       bail!(
         "{} is already installed",
         pretty::plugin_version(&self.name, version.version_str())
       );
     }
 
+    let stamp_path = download_stamp_path(&self.download_dir, version_str);
+    if download_complete(&self.download_dir, version_str) {
+      info!(
+        "Using cached download of {}",
+        pretty::plugin_version(&self.name, version_str)
+      );
+
+      // The stamp's mtime tracks when the download was last used
+      fs::write(&stamp_path, "")?;
+      return Ok(Some(()));
+    }
+
+    // Whatever is there was left behind by a failed or interrupted download
+    self.rm_download_dir(version)?;
     fs::create_dir_all(&version_download_dir)?;
-    let bar = auto_bar();
-    let result = self.run_script(
+    let result = self.run_script_logged(
       Some((
-        &bar,
+        bar,
         &format!(
           "Downloading {}...",
           pretty::plugin_version(&self.name, version.version_str())
@@ -257,17 +389,70 @@ impl PluginScripts {
         (ASDF_INSTALL_PATH, &version_install_dir.to_string_lossy()),
         (ASDF_DOWNLOAD_PATH, &version_download_dir.to_string_lossy()),
       ],
+      version,
       |_| Some(()),
-    )?;
+    );
 
-    bar.finish_with_message(format!(
-      "Downloaded {}",
-      pretty::plugin_version(&self.name, version.version_str())
-    ));
-    Ok(result)
+    match result {
+      Ok(result) => {
+        fs::write(&stamp_path, "")?;
+        Ok(result)
+      }
+      Err(err) => {
+        trace!("Download failed, removing {version_download_dir:?}");
+        if let Err(rm_err) = self.rm_download_dir(version) {
+          warn!("Failed to clean up failed download: {rm_err}");
+        }
+
+        Err(err)
+      }
+    }
+  }
+
+  /// Flag the download dir of `version` as in use by this process, so pruning the
+  /// download cache leaves it alone until [`PluginScripts::unlock_download`].
+  pub fn lock_download(&self, version: &Version) -> Result<()> {
+    fs::create_dir_all(&self.download_dir)?;
+    let lock_path = download_lock_path(&self.download_dir, version.version_str());
+    fs::write(lock_path, format!("{}\n", std::process::id()))?;
+    Ok(())
   }
 
-  pub fn install(&self, version: &Version, concurrency: Option<usize>) -> Result<Option<String>> {
+  /// Release the download dir of `version` after an install. It's kept in the
+  /// cache if `keep` is set, and removed otherwise.
+  pub fn unlock_download(&self, version: &Version, keep: bool) -> Result<()> {
+    if !keep {
+      return self.rm_version_download(version);
+    }
+
+    let lock_path = download_lock_path(&self.download_dir, version.version_str());
+    if lock_path.exists() {
+      fs::remove_file(lock_path)?;
+    }
+
+    Ok(())
+  }
+
+  /// Flag the download dir of `version` as intact. Plugins without a download
+  /// script fill it from their install script, so this is only known once that
+  /// succeeded.
+  pub fn mark_download_complete(&self, version: &Version) -> Result<()> {
+    let version_str = version.version_str();
+    if self.download_dir.join(version_str).is_dir() {
+      fs::write(download_stamp_path(&self.download_dir, version_str), "")?;
+    }
+
+    Ok(())
+  }
+
+  /// Run the install script, reporting progress on `bar`. The bar is left for the
+  /// caller to finish.
+  pub fn install(
+    &self,
+    version: &Version,
+    concurrency: Option<usize>,
+    bar: &ProgressBar,
+  ) -> Result<Option<String>> {
     trace!(
       "Installing version {version:?} for plugin `{:?}` to `{:?}`",
       self.plugin_dir,
@@ -285,6 +470,27 @@ impl PluginScripts {
     let version_str = version.version_str();
     let version_download_dir = self.download_dir.join(version.version_str());
     let version_install_dir = self.install_dir.join(version.version_str());
+    let marker_path = install_marker_path(&self.install_dir, version_str);
+    let staging_dir = install_staging_path(&self.install_dir, version_str);
+
+    // The marker holds the pid of the install, so an install that's still running
+    // in another shell is left alone
+    if lock_active(&marker_path) {
+      return Err(PluginScriptError::InstallInProgress {
+        plugin: self.name.clone(),
+        version: version.raw(),
+        pid: fs::read_to_string(&marker_path)?.trim().to_owned(),
+      })?;
+    }
+
+    if marker_path.exists() {
+      warn!(
+        "Removing incomplete install of {} left behind by an earlier run",
+        pretty::plugin_version(&self.name, version_str)
+      );
+      self.rm_version(version)?;
+    }
+
     if version_install_dir.is_dir() {
       return Err(PluginScriptError::VersionAlreadyInstalled {
         plugin: self.name.clone(),
@@ -292,16 +498,26 @@ impl PluginScripts {
       })?;
     }
 
-    fs::create_dir_all(&version_install_dir)?;
+    // The marker goes next to the install dir rather than into it, since install
+    // scripts are free to wipe their install dir. Installs are built in a staging
+    // dir and only moved into place once the script succeeded. Scripts still get
+    // the final path, which links to the staging dir until then, since a lot of
+    // builds hardcode their prefix.
+    fs::create_dir_all(&self.install_dir)?;
+    fs::write(&marker_path, format!("{}\n", std::process::id()))?;
+    fs::create_dir_all(&staging_dir)?;
+    std::os::unix::fs::symlink(
+      staging_dir.file_name().unwrap_or_default(),
+      &version_install_dir,
+    )?;
 
     let concurrency = concurrency
       .or_else(|| num_threads::num_threads().map(|num| num.get()))
       .unwrap_or(1);
 
-    let bar = auto_bar();
-    let result = self.run_script(
+    let result = self.run_script_logged(
       Some((
-        &bar,
+        bar,
         &format!(
           "Installing {}...",
           pretty::plugin_version(&self.name, version.version_str())
@@ -315,14 +531,61 @@ impl PluginScripts {
         (ASDF_DOWNLOAD_PATH, &version_download_dir.to_string_lossy()),
         (ASDF_CONCURRENCY, &concurrency.to_string()),
       ],
+      version,
       Some,
+    );
+
+    match result {
+      Ok(result) => {
+        self.finish_install(version)?;
+        Ok(result)
+      }
+      Err(err) => {
+        trace!("Install failed, removing {version_install_dir:?}");
+        if let Err(rm_err) = self.rm_version(version) {
+          warn!("Failed to clean up failed install: {rm_err}");
+        }
+
+        Err(err)
+      }
+    }
+  }
+
+  /// Move a finished install from its staging dir into place. Scripts that
+  /// replaced the link with a dir of their own already installed in place.
+  fn finish_install(&self, version: &Version) -> Result<()> {
+    let version_str = version.version_str();
+    let version_install_dir = self.install_dir.join(version_str);
+    let staging_dir = install_staging_path(&self.install_dir, version_str);
+
+    match fs::symlink_metadata(&version_install_dir) {
+      Ok(meta) if !meta.file_type().is_symlink() => fs::remove_dir_all(&staging_dir)?,
+      Ok(_) => {
+        fs::remove_file(&version_install_dir)?;
+        fs::rename(&staging_dir, &version_install_dir)?;
+      }
+      Err(_) => fs::rename(&staging_dir, &version_install_dir)?,
+    }
+
+    // Hooks commonly install packages into the version afterwards, so the checksum
+    // of what the install script built is recorded now
+    fs::write(
+      install_checksum_path(&self.install_dir, version_str),
+      checksum_dir(&version_install_dir)?,
     )?;
 
-    bar.finish_with_message(format!(
-      "Installed {}",
-      pretty::plugin_version(&self.name, version.version_str())
-    ));
-    Ok(result)
+    fs::remove_file(install_marker_path(&self.install_dir, version_str))?;
+    Ok(())
+  }
+
+  /// The checksum of `version` as its install script left it, see [`checksum_dir`].
+  /// Versions installed before checksums were recorded are checksummed as they are.
+  pub fn install_checksum(&self, version: &Version) -> Result<String> {
+    let checksum_path = install_checksum_path(&self.install_dir, version.version_str());
+    match fs::read_to_string(checksum_path) {
+      Ok(checksum) => Ok(checksum.trim().to_owned()),
+      Err(_) => Ok(checksum_dir(self.get_version_path(version)?)?),
+    }
   }
 
   pub fn has_uninstall(&self) -> bool {
@@ -331,20 +594,62 @@ impl PluginScripts {
 
   pub fn rm_version(&self, version: &Version) -> Result<()> {
     let version_dir = self.install_dir.join(version.version_str());
-    if !version_dir.is_dir() {
-      return Ok(());
+    match fs::symlink_metadata(&version_dir) {
+      Ok(meta) if meta.file_type().is_symlink() => fs::remove_file(&version_dir)?,
+      Ok(_) => fs::remove_dir_all(&version_dir)?,
+      Err(_) => {}
+    }
+
+    let staging_dir = install_staging_path(&self.install_dir, version.version_str());
+    if staging_dir.is_dir() {
+      fs::remove_dir_all(&staging_dir)?;
     }
 
-    Ok(fs::remove_dir_all(&version_dir)?)
+    // Only remove the marker once the dir is gone, so a failed removal
+    // doesn't leave a broken install that looks complete
+    let marker_path = install_marker_path(&self.install_dir, version.version_str());
+    if marker_path.exists() {
+      fs::remove_file(&marker_path)?;
+    }
+
+    let used_path = used_stamp_path(&self.install_dir, version.version_str());
+    if used_path.exists() {
+      fs::remove_file(&used_path)?;
+    }
+
+    let checksum_path = install_checksum_path(&self.install_dir, version.version_str());
+    if checksum_path.exists() {
+      fs::remove_file(&checksum_path)?;
+    }
+
+    Ok(())
   }
 
   pub fn rm_version_download(&self, version: &Version) -> Result<()> {
+    self.rm_download_dir(version)?;
+
+    let lock_path = download_lock_path(&self.download_dir, version.version_str());
+    if lock_path.exists() {
+      fs::remove_file(lock_path)?;
+    }
+
+    Ok(())
+  }
+
+  /// Remove the download dir of `version` and its stamp, but not its lock.
+  fn rm_download_dir(&self, version: &Version) -> Result<()> {
     let dl_dir = self.download_dir.join(version.version_str());
-    if !dl_dir.is_dir() {
-      return Ok(());
+    if dl_dir.is_dir() {
+      fs::remove_dir_all(&dl_dir)?;
+    }
+
+    // Same as for installs, the stamp goes last
+    let stamp_path = download_stamp_path(&self.download_dir, version.version_str());
+    if stamp_path.exists() {
+      fs::remove_file(stamp_path)?;
     }
 
-    Ok(fs::remove_dir_all(&dl_dir)?)
+    Ok(())
   }
 
   pub fn uninstall(&self, progress: Progress, version: &Version) -> Result<Option<String>> {
@@ -453,9 +758,14 @@ impl PluginScripts {
     Ok(output)
   }
 
+  /// Where `version` is or will be installed, whether the install is complete or not.
+  pub fn version_install_path(&self, version: &Version) -> PathBuf {
+    self.install_dir.join(version.version_str())
+  }
+
   pub fn get_version_path(&self, version: &Version) -> Result<PathBuf> {
     let result = self.install_dir.join(version.raw());
-    if !result.is_dir() {
+    if !install_complete(&self.install_dir, &version.raw()) {
       return Err(PluginScriptError::VersionNotInstalled {
         plugin: self.name.clone(),
         version: version.raw(),
@@ -467,116 +777,140 @@ impl PluginScripts {
 
   // Env modification
 
-  pub fn exec_env_echo(&self, version: &Version) -> Result<Option<Vec<(String, String)>>> {
-    // TODO: Do we need to support adding to path entries here?
-
-    let version_dir = self.install_dir.join(version.version_str());
+  /// Evaluate the plugin's `exec-env` script and return the changes it makes to the
+  /// env. The script is sourced in a clean bash subshell, and the env is diffed
+  /// before and after, so this works no matter how the script sets its vars.
+  /// Results are cached per version and invalidated when anything in the plugin's
+  /// `bin` directory changes, since `exec-env` often sources helpers from there.
+  pub fn exec_env(&self, version: &Version) -> Result<Option<Env>> {
     let exec_path = self.plugin_dir.join("bin/exec-env");
+
+    // Earlier versions generated this script inside the plugin checkout
+    let legacy_echo_path = self.plugin_dir.join("bin/exec-env-echo");
+    if legacy_echo_path.is_file() {
+      trace!("Removing legacy exec-env-echo script");
+      let _ = fs::remove_file(&legacy_echo_path);
+    }
+
     if !exec_path.is_file() {
       return Ok(None);
     }
 
-    // This is an alternative version of printing the env, which should
-    // be faster since it doesn't need to diff the entire env.
-    // By replacing every instance of `export` with `echo`, we make
-    // the script output the paths that should be added.
-    // This might be less accurate and might not capture everything since
-    // the script can source another script that exports vars.
-    let exec_echo_path = self.plugin_dir.join("bin/exec-env-echo");
-    if !exec_echo_path.is_file() {
-      trace!("Generating exec-env-echo script");
-      let script_contents = fs::read_to_string(&exec_path)?;
-      let echo_script = EXPORT_ECHO_RE.replace_all(&script_contents, "echo ");
-
-      trace!("Wrote exec-env-echo script:\n{echo_script}");
-      fs::write(&exec_echo_path, echo_script.as_bytes())?;
-
-      // Make the new script executable
-      fs::set_permissions(&exec_echo_path, PermissionsExt::from_mode(0o0755))?;
+    let bin_hash = checksum_dir(self.plugin_dir.join("bin"))?;
+    let cache_path = exec_env_cache_path(&self.exec_env_cache_dir, version);
+    if let Some(cached) = read_exec_env_cache(&cache_path, &bin_hash) {
+      trace!("Using cached exec-env for {version:?}");
+      return Ok(Some(cached));
     }
 
-    let output = self.run_script(
-      None,
-      &exec_echo_path,
-      &[
-        (ASDF_INSTALL_TYPE, version.install_type()),
-        (ASDF_INSTALL_VERSION, &version.raw()),
-        (ASDF_INSTALL_PATH, &*version_dir.to_string_lossy()),
-      ],
-      |output| output,
-    )?;
+    let env = self.exec_env_diff(&exec_path, version)?;
+    write_exec_env_cache(&cache_path, &bin_hash, &env)?;
 
-    let parts = output
-      .split('\n')
-      .filter_map(|line| line.split_once('='))
-      .map(|(key, val)| (key.to_owned(), val.to_owned()))
-      .collect::<Vec<_>>();
-
-    if !parts.is_empty() {
-      Ok(Some(parts))
-    } else {
-      Ok(None)
-    }
+    Ok(Some(env))
   }
 
-  // NOTE: This is potentially a better version of getting
-  // an env diff, but it's a lot slower than simply replacing
-  // export with echo.
-  pub fn _exec_env_diff(&self, version: &Version) -> Result<Option<Vec<(String, String)>>> {
-    // TODO: Do we need to support adding to path entries here?
+  fn exec_env_diff(&self, exec_path: &Path, version: &Version) -> Result<Env> {
+    log_script(exec_path);
 
-    // This is pretty stupid, but there's no way for us to know
-    // what vars are being changed unless we actually run the script
-    // and compare the env before and after.
     let version_dir = self.install_dir.join(version.version_str());
-    let exec_path = self.plugin_dir.join("bin/exec-env");
-    if !exec_path.is_file() {
-      return Ok(None);
-    }
 
-    // We use a double newline to delimit input and output, since we can
-    // split by that afterwards and take the first and last result.
-    // No matter what the script outputs, this will always work since
-    // env will always print one line per env var, and escape characters itself.
+    // Print the env before and after sourcing the script, with a marker around the
+    // script. Entries are null-delimited, so values containing newlines survive.
+    // The script's own output is discarded, since it is not part of the env. A
+    // script that exits or fails under `set -e` takes the shell with it, so the
+    // second marker only shows up if the script finished.
     let run_str = format!(
-      r#"env;echo;{}={} {}={} {}={} . "{}";echo;env;"#,
-      ASDF_INSTALL_TYPE,
-      ASDF_INSTALL_VERSION,
-      ASDF_INSTALL_PATH,
-      version.install_type(),
-      version.raw(),
-      version_dir.to_string_lossy(),
-      exec_path.to_string_lossy(),
+      r#"env -0; printf '%s\0' {EXEC_ENV_SEPARATOR}; . "$0" >/dev/null; printf '%s\0' {EXEC_ENV_SEPARATOR}; env -0"#,
     );
 
-    let output = Command::new("bash")
-      .args(&["-c", &run_str])
+    let mut cmd = Command::new("bash");
+    cmd
+      .args(["-c", &run_str, &exec_path.to_string_lossy()])
+      .env_clear()
       .env("PATH", &self.script_env_path)
-      .output()?;
+      .env(ASDF_INSTALL_TYPE, version.install_type())
+      .env(ASDF_INSTALL_VERSION, version.raw())
+      .env(ASDF_INSTALL_PATH, version_dir.to_string_lossy().as_ref());
+
+    for key in EXEC_ENV_PASSTHROUGH {
+      if let Some(val) = std::env::var_os(key) {
+        cmd.env(key, val);
+      }
+    }
 
+    let output = cmd.output()?;
     let stdout = String::from_utf8_lossy(&output.stdout);
-    let mut parts = stdout.split("\n\n");
-    let env_before = parts
-      .next()
-      .unwrap_or("")
-      .trim()
-      .split('\n')
+    let entries = stdout.split('\0').collect::<Vec<_>>();
+    let markers = entries
+      .iter()
+      .enumerate()
+      .filter(|(_, entry)| **entry == EXEC_ENV_SEPARATOR)
+      .map(|(i, _)| i)
       .collect::<Vec<_>>();
 
-    let env_before_set = HashSet::<&str>::from_iter(env_before);
-
-    let env_after = parts
-      .last()
-      .unwrap_or("")
-      .trim()
-      .split('\n')
-      .filter(|line| !env_before_set.contains(line))
-      .filter_map(|line| line.split_once('='))
-      .filter(|(key, _)| !IGNORED_ENV_VARS.contains(key))
-      .map(|(key, val)| (key.to_owned(), val.to_owned()))
+    let (start, end) = match markers[..] {
+      [start, end] if output.status.success() => (start, end),
+      _ => {
+        return Err(PluginScriptError::ExecEnvFailed(
+          String::from_utf8_lossy(&output.stderr).trim().to_owned(),
+        ))?
+      }
+    };
+
+    let before = entries[..start]
+      .iter()
+      .filter_map(|entry| entry.split_once('='))
+      .collect::<HashMap<_, _>>();
+
+    let after = entries[end + 1..]
+      .iter()
+      .filter_map(|entry| entry.split_once('='))
+      .collect::<Vec<_>>();
+
+    Ok(diff_env(&before, &after))
+  }
+
+  pub fn rm_exec_env_cache(&self, version: &Version) -> Result<()> {
+    let cache_path = exec_env_cache_path(&self.exec_env_cache_dir, version);
+    if !cache_path.is_file() {
+      return Ok(());
+    }
+
+    Ok(fs::remove_file(cache_path)?)
+  }
+
+  // Logs
+
+  /// The log with the output of the download and install scripts of `version`.
+  pub fn log_path(&self, version: &Version) -> PathBuf {
+    self.log_dir.join(format!("{}.log", version.version_str()))
+  }
+
+  /// Remove the log of an earlier attempt, so the log only covers the latest
+  /// install.
+  pub fn reset_log(&self, version: &Version) -> Result<()> {
+    let log_path = self.log_path(version);
+    if !log_path.is_file() {
+      return Ok(());
+    }
+
+    Ok(fs::remove_file(log_path)?)
+  }
+
+  /// All logs of this plugin, the most recently written one first.
+  pub fn logs(&self) -> Result<Vec<PathBuf>> {
+    if !self.log_dir.is_dir() {
+      return Ok(vec![]);
+    }
+
+    let mut logs = fs::read_dir(&self.log_dir)?
+      .filter_map(|entry| entry.ok())
+      .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "log"))
+      .filter_map(|entry| Some((entry.metadata().ok()?.modified().ok()?, entry.path())))
       .collect::<Vec<_>>();
 
-    Ok(Some(env_after))
+    logs.sort_by_key(|(modified, _)| std::cmp::Reverse(*modified));
+    Ok(logs.into_iter().map(|(_, path)| path).collect())
   }
 
   // Latest resolution
@@ -607,7 +941,7 @@ impl PluginScripts {
             output
               .trim()
               .split(' ')
-              .filter(|version| !LATEST_STABLE_RE.is_match(version))
+              .filter(|version| is_stable(version))
               .last()
               .map(Version::parse)
           },
@@ -722,32 +1056,37 @@ impl PluginScripts {
   // Helpers
 
   pub fn get_env(&self, version: &Version) -> Result<Env> {
-    let mut env = Env::default();
+    Ok(self.get_version_env(version)?.combined())
+  }
 
+  /// Get the env of a version, split by the script that each part came from.
+  pub fn get_version_env(&self, version: &Version) -> Result<VersionEnv> {
     // first, see if there's an exec-env
-    if let Some(exec_env) = self.exec_env_echo(version)? {
-      env.vars.extend(exec_env);
-    }
+    let exec_env = self.exec_env(version)?.unwrap_or_default();
 
     // now, add the bin paths to our path
-    env.path.extend(self.list_bin_paths(version)?);
+    let mut bin_paths = Env::default();
+    for entry in self.list_bin_paths(version)? {
+      bin_paths.add_path(entry);
+    }
 
-    if env.path.is_empty() {
+    if bin_paths.path.is_empty() && exec_env.path.is_empty() {
       let version_path = self.install_dir.join(version.version_str());
 
       // Check if there's a bin folder in our install
       let maybe_bin_path = version_path.join("bin");
       if maybe_bin_path.is_dir() {
-        env
-          .path
-          .insert(maybe_bin_path.to_string_lossy().to_string());
+        bin_paths.add_path(maybe_bin_path.to_string_lossy());
       } else {
         // Just add the install folder
-        env.path.insert(version_path.to_string_lossy().to_string());
+        bin_paths.add_path(version_path.to_string_lossy());
       }
     }
 
-    Ok(env)
+    Ok(VersionEnv {
+      exec_env,
+      bin_paths,
+    })
   }
 
   pub fn resolve(&self, version: &str) -> Result<Option<Version>> {
@@ -767,3 +1106,363 @@ fn log_script(path: &Path) {
   let contents = fs::read_to_string(&path).unwrap_or_else(|_| "".to_owned());
   trace!("Running script `{path:?}` with content: \n{contents}");
 }
+
+/// Compute the changes between two envs. Vars that were added or changed are
+/// returned as vars, and new entries in `PATH` are returned as path entries in
+/// the order they appear in. Removed vars are ignored.
+fn diff_env(before: &HashMap<&str, &str>, after: &[(&str, &str)]) -> Env {
+  let mut env = Env::default();
+  let before_path = before
+    .get("PATH")
+    .map(|path| path.split(':').collect::<Vec<_>>())
+    .unwrap_or_default();
+
+  for (key, val) in after {
+    if *key == "PATH" {
+      val
+        .split(':')
+        .filter(|entry| !entry.is_empty() && !before_path.contains(entry))
+        .for_each(|entry| env.add_path(entry));
+
+      continue;
+    }
+
+    if IGNORED_ENV_VARS.contains(key) || before.get(key) == Some(val) {
+      continue;
+    }
+
+    env.vars.insert(key.to_string(), val.to_string());
+  }
+
+  env
+}
+
+/// Check whether `version` looks like a stable release, as opposed to e.g. a
+/// release candidate or a nightly build.
+pub fn is_stable(version: &str) -> bool {
+  !LATEST_STABLE_RE.is_match(version)
+}
+
+/// Path of the marker that flags an unfinished install of `version`. `install_dir`
+/// is the dir containing all installed versions of a plugin.
+pub fn install_marker_path<P: AsRef<Path>>(install_dir: P, version: &str) -> PathBuf {
+  install_dir.as_ref().join(format!(".{version}.installing"))
+}
+
+/// Path of the dir an install of `version` is built in before it's moved into
+/// place. `install_dir` is the dir containing all installed versions of a plugin.
+pub fn install_staging_path<P: AsRef<Path>>(install_dir: P, version: &str) -> PathBuf {
+  install_dir.as_ref().join(format!(".{version}.staging"))
+}
+
+/// Path of the checksum of `version`, recorded once its install script finished.
+/// `install_dir` is the dir containing all installed versions of a plugin.
+pub fn install_checksum_path<P: AsRef<Path>>(install_dir: P, version: &str) -> PathBuf {
+  install_dir.as_ref().join(format!(".{version}.checksum"))
+}
+
+/// Path of the stamp whose mtime is when `version` was last activated.
+/// `install_dir` is the dir containing all installed versions of a plugin.
+pub fn used_stamp_path<P: AsRef<Path>>(install_dir: P, version: &str) -> PathBuf {
+  install_dir.as_ref().join(format!(".{version}.used"))
+}
+
+/// Check whether `version` was installed completely. `install_dir` is the dir
+/// containing all installed versions of a plugin.
+pub fn install_complete<P: AsRef<Path>>(install_dir: P, version: &str) -> bool {
+  let install_dir = install_dir.as_ref();
+  install_dir.join(version).is_dir() && !install_marker_path(install_dir, version).exists()
+}
+
+/// Path of the stamp that flags a finished download of `version`. Its mtime is
+/// when the download was last used. `download_dir` is the dir containing all
+/// downloads of a plugin.
+pub fn download_stamp_path<P: AsRef<Path>>(download_dir: P, version: &str) -> PathBuf {
+  download_dir.as_ref().join(format!(".{version}.downloaded"))
+}
+
+/// Path of the lock holding the pid of the process that is using the download of
+/// `version`. `download_dir` is the dir containing all downloads of a plugin.
+pub fn download_lock_path<P: AsRef<Path>>(download_dir: P, version: &str) -> PathBuf {
+  download_dir.as_ref().join(format!(".{version}.lock"))
+}
+
+/// Check whether the download of `version` finished and can be reused.
+pub fn download_complete<P: AsRef<Path>>(download_dir: P, version: &str) -> bool {
+  let download_dir = download_dir.as_ref();
+  download_dir.join(version).is_dir() && download_stamp_path(download_dir, version).exists()
+}
+
+/// The exec-env cache file of `version`. Path versions are absolute paths and the
+/// system version has none, so the name is derived from the raw version instead.
+fn exec_env_cache_path<P: AsRef<Path>>(cache_dir: P, version: &Version) -> PathBuf {
+  let mut hasher = twox_hash::XxHash64::with_seed(0);
+  hasher.write(version.raw().as_bytes());
+  cache_dir.as_ref().join(format!(
+    "{}-{:016x}",
+    version.install_type(),
+    hasher.finish()
+  ))
+}
+
+#[derive(Serialize, Deserialize)]
+struct ExecEnvCache {
+  bin_hash: String,
+  path: Vec<String>,
+  vars: BTreeMap<String, String>,
+}
+
+fn read_exec_env_cache(path: &Path, bin_hash: &str) -> Option<Env> {
+  let contents = fs::read_to_string(path).ok()?;
+  let cache = toml::from_str::<ExecEnvCache>(&contents).ok()?;
+  if cache.bin_hash != bin_hash {
+    trace!("Cached exec-env at {path:?} is outdated");
+    return None;
+  }
+
+  Some(Env {
+    vars: cache.vars,
+    path: cache.path,
+  })
+}
+
+fn write_exec_env_cache(path: &Path, bin_hash: &str, env: &Env) -> Result<()> {
+  if let Some(parent) = path.parent() {
+    fs::create_dir_all(parent)?;
+  }
+
+  let cache = ExecEnvCache {
+    bin_hash: bin_hash.to_owned(),
+    path: env.path.clone(),
+    vars: env.vars.clone(),
+  };
+
+  fs::write(path, toml::to_string(&cache)?)?;
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn diff_env_vars_and_path() {
+    let before = HashMap::from([("FOO", "1"), ("BAR", "2"), ("PATH", "/usr/bin:/bin")]);
+    let after = [
+      ("FOO", "1"),
+      ("BAR", "3"),
+      ("BAZ", "a=b"),
+      ("SHLVL", "2"),
+      ("PATH", "/opt/b:/usr/bin:/opt/a:/bin"),
+    ];
+
+    let env = diff_env(&before, &after);
+    assert_eq!(env.path, vec!["/opt/b", "/opt/a"]);
+    assert_eq!(
+      env.vars.into_iter().collect::<Vec<_>>(),
+      vec![
+        ("BAR".to_owned(), "3".to_owned()),
+        ("BAZ".to_owned(), "a=b".to_owned()),
+      ]
+    );
+  }
+
+  #[test]
+  fn exec_env_cache_roundtrip() {
+    let workdir = tempfile::tempdir().expect("failed to create temp dir");
+    let path = workdir.as_ref().join("foo/1.0");
+
+    let mut env = Env::default();
+    env.add_path("/foo/bin");
+    env.vars.insert("FOO".to_owned(), "bar".to_owned());
+    write_exec_env_cache(&path, "abc", &env).expect("failed to write cache");
+
+    let cached = read_exec_env_cache(&path, "abc").expect("failed to read cache");
+    assert_eq!(cached.path, env.path);
+    assert_eq!(cached.vars, env.vars);
+
+    assert!(read_exec_env_cache(&path, "def").is_none());
+  }
+
+  #[test]
+  fn exec_env_for_path_versions() {
+    let workdir = tempfile::tempdir().expect("failed to create temp dir");
+    let root = workdir.as_ref();
+    let (plugins, cache, tool_dir) = (root.join("plugins"), root.join("cache"), root.join("tool"));
+
+    let bin_dir = plugins.join("foo/bin");
+    fs::create_dir_all(&bin_dir).expect("failed to create dirs");
+    fs::create_dir(&tool_dir).expect("failed to create dir");
+    fs::write(bin_dir.join("helpers"), "FOO=1").expect("failed to write helpers");
+    fs::write(
+      bin_dir.join("exec-env"),
+      ". \"$(dirname \"$0\")/helpers\"; export FOO_HOME=\"$ASDF_INSTALL_PATH\" FOO",
+    )
+    .expect("failed to write exec-env");
+
+    let scripts = PluginScripts::new("foo", &plugins, root, root, &cache, root, &[])
+      .expect("failed to create scripts");
+    let version = Version::parse(&format!("path:{}", tool_dir.to_string_lossy()));
+
+    // The cache file has to stay inside the cache dir, and must not be the same for
+    // different kinds of versions
+    let cache_path = exec_env_cache_path(cache.join("foo"), &version);
+    assert_eq!(cache_path.parent(), Some(cache.join("foo").as_path()));
+    assert_ne!(
+      cache_path,
+      exec_env_cache_path(cache.join("foo"), &Version::System)
+    );
+
+    let env = scripts
+      .exec_env(&version)
+      .expect("failed to run exec-env")
+      .expect("exec-env is missing");
+    assert_eq!(env.vars["FOO_HOME"], tool_dir.to_string_lossy());
+    assert_eq!(env.vars["FOO"], "1");
+    assert!(cache_path.is_file());
+
+    // Changing a sourced helper invalidates the cache
+    fs::write(bin_dir.join("helpers"), "FOO=2").expect("failed to write helpers");
+    let env = scripts
+      .exec_env(&version)
+      .expect("failed to run exec-env")
+      .expect("exec-env is missing");
+    assert_eq!(env.vars["FOO"], "2");
+  }
+
+  #[test]
+  fn exec_env_that_exits() {
+    let workdir = tempfile::tempdir().expect("failed to create temp dir");
+    let root = workdir.as_ref();
+    let (plugins, cache) = (root.join("plugins"), root.join("cache"));
+
+    let bin_dir = plugins.join("foo/bin");
+    fs::create_dir_all(&bin_dir).expect("failed to create dirs");
+    let scripts = PluginScripts::new("foo", &plugins, root, root, &cache, root, &[])
+      .expect("failed to create scripts");
+    let version = Version::parse("1.0");
+    let cache_path = exec_env_cache_path(cache.join("foo"), &version);
+
+    for script in [
+      "export FOO=1; echo broken >&2; exit 0",
+      "set -e; export FOO=1; echo broken >&2; false",
+    ] {
+      fs::write(bin_dir.join("exec-env"), script).expect("failed to write exec-env");
+
+      let err = scripts.exec_env(&version).unwrap_err();
+      assert!(err.to_string().contains("broken"), "{err}");
+      assert!(!cache_path.exists());
+    }
+
+    // A failing last command is fine, as long as the script finishes
+    fs::write(bin_dir.join("exec-env"), "export FOO=1; false").expect("failed to write exec-env");
+    let env = scripts
+      .exec_env(&version)
+      .expect("failed to run exec-env")
+      .expect("exec-env is missing");
+    assert_eq!(env.vars["FOO"], "1");
+  }
+
+  fn install_scripts(root: &Path, script: &str) -> PluginScripts {
+    let bin_dir = root.join("plugins/foo/bin");
+    fs::create_dir_all(&bin_dir).expect("failed to create dirs");
+    fs::write(bin_dir.join("install"), format!("#!/bin/sh\n{script}\n"))
+      .expect("failed to write install script");
+    fs::set_permissions(
+      bin_dir.join("install"),
+      std::os::unix::fs::PermissionsExt::from_mode(0o755),
+    )
+    .expect("failed to chmod install script");
+
+    PluginScripts::new(
+      "foo",
+      root.join("plugins"),
+      root.join("installs"),
+      root.join("downloads"),
+      root.join("cache"),
+      root.join("logs"),
+      &[],
+    )
+    .expect("failed to create scripts")
+  }
+
+  #[test]
+  fn staged_installs() {
+    let workdir = tempfile::tempdir().expect("failed to create temp dir");
+    let root = workdir.as_ref();
+    let install_dir = root.join("installs/foo");
+    let bar = ProgressBar::hidden();
+    let version = Version::parse("1.0");
+
+    // Scripts see the final path, but build in the staging dir
+    let scripts = install_scripts(
+      root,
+      "test -d \"$ASDF_INSTALL_PATH/../.1.0.staging\" && touch \"$ASDF_INSTALL_PATH/tool\"",
+    );
+    scripts
+      .install(&version, None, &bar)
+      .expect("failed to install");
+    assert!(fs::symlink_metadata(install_dir.join("1.0"))
+      .unwrap()
+      .is_dir());
+    assert!(install_dir.join("1.0/tool").is_file());
+    assert!(!install_staging_path(&install_dir, "1.0").exists());
+    assert!(scripts.version_installed(&version));
+
+    // A failed install leaves nothing behind
+    let scripts = install_scripts(root, "touch \"$ASDF_INSTALL_PATH/tool\"; exit 1");
+    assert!(scripts.install(&Version::parse("2.0"), None, &bar).is_err());
+    assert!(fs::symlink_metadata(install_dir.join("2.0")).is_err());
+    assert!(!install_staging_path(&install_dir, "2.0").exists());
+    assert!(!install_marker_path(&install_dir, "2.0").exists());
+  }
+
+  #[test]
+  fn concurrent_installs() {
+    let workdir = tempfile::tempdir().expect("failed to create temp dir");
+    let root = workdir.as_ref();
+    let install_dir = root.join("installs/foo");
+    let bar = ProgressBar::hidden();
+    let version = Version::parse("1.0");
+    let scripts = install_scripts(root, "touch \"$ASDF_INSTALL_PATH/tool\"");
+
+    // An install that is still running is left alone
+    fs::create_dir_all(install_staging_path(&install_dir, "1.0")).unwrap();
+    fs::write(
+      install_marker_path(&install_dir, "1.0"),
+      std::process::id().to_string(),
+    )
+    .unwrap();
+    let err = scripts.install(&version, None, &bar).unwrap_err();
+    assert!(err.to_string().contains("being installed by pid"));
+    assert!(install_staging_path(&install_dir, "1.0").is_dir());
+
+    // Leftovers of a process that's gone are cleaned up
+    let mut child = Command::new("true").spawn().expect("failed to spawn");
+    let dead_pid = child.id();
+    child.wait().expect("failed to wait");
+    fs::write(
+      install_marker_path(&install_dir, "1.0"),
+      dead_pid.to_string(),
+    )
+    .unwrap();
+    scripts
+      .install(&version, None, &bar)
+      .expect("failed to install");
+    assert!(scripts.version_installed(&version));
+  }
+
+  #[test]
+  fn incomplete_installs() {
+    let workdir = tempfile::tempdir().expect("failed to create temp dir");
+    let install_dir = workdir.as_ref();
+    assert!(!install_complete(install_dir, "1.0"));
+
+    fs::create_dir(install_dir.join("1.0")).expect("failed to create dir");
+    fs::write(install_marker_path(install_dir, "1.0"), "").expect("failed to write marker");
+    assert!(!install_complete(install_dir, "1.0"));
+
+    fs::remove_file(install_marker_path(install_dir, "1.0")).expect("failed to remove marker");
+    assert!(install_complete(install_dir, "1.0"));
+  }
+}
@@ -18,8 +18,11 @@ pub trait Shell {
 
 #[derive(Debug, Default)]
 pub struct ShellState {
-  add_path: HashSet<String>,
+  /// Path entries to prepend, in order of precedence.
+  add_path: Vec<String>,
   remove_path: HashSet<String>,
+  /// Replaces the whole path when set, instead of changing the current one.
+  replace_path: Option<Vec<String>>,
   set_var: HashMap<String, String>,
   unset_var: HashSet<String>,
 }
@@ -41,14 +44,20 @@ impl ShellState {
 
   pub fn add_path(&mut self, entry: &str) {
     self.remove_path.remove(entry);
-    self.add_path.insert(entry.to_owned());
+    if !self.add_path.iter().any(|existing| existing == entry) {
+      self.add_path.push(entry.to_owned());
+    }
   }
 
   pub fn remove_path(&mut self, entry: &str) {
-    self.add_path.remove(entry);
+    self.add_path.retain(|existing| existing != entry);
     self.remove_path.insert(entry.to_owned());
   }
 
+  pub fn replace_path(&mut self, entries: Vec<String>) {
+    self.replace_path = Some(entries);
+  }
+
   pub fn apply(&mut self, env: &Env) {
     for (key, val) in &env.vars {
       self.set(key, val);
@@ -71,18 +80,27 @@ impl ShellState {
 }
 
 pub(crate) fn apply_bashlike(state: &ShellState) -> String {
-  let path = std::env::var("PATH").unwrap_or_default();
-  let prev_path = path
-    .split(':')
-    // We filter out both add and remove here, since
-    // we want all appended items to be at the front of
-    // the new path afterwards.
-    .filter(|entry| !state.remove_path.contains(*entry) && !state.add_path.contains(*entry))
-    .map(|entry| entry.to_owned());
-
-  let mut new_path = state.add_path.iter().cloned().collect::<Vec<_>>();
-  new_path.extend(prev_path);
-  let path_str = format!("export PATH={};", new_path.join(":"));
+  let new_path = match &state.replace_path {
+    Some(replace_path) => replace_path.clone(),
+    None => {
+      let path = std::env::var("PATH").unwrap_or_default();
+      let prev_path = path
+        .split(':')
+        // We filter out both add and remove here, since
+        // we want all appended items to be at the front of
+        // the new path afterwards.
+        .filter(|entry| {
+          !state.remove_path.contains(*entry) && !state.add_path.iter().any(|added| added == entry)
+        })
+        .map(|entry| entry.to_owned());
+
+      let mut new_path = state.add_path.clone();
+      new_path.extend(prev_path);
+      new_path
+    }
+  };
+
+  let path_str = format!("export PATH={};", quote(&new_path.join(":")));
 
   let unset_str = state
     .unset_var
@@ -96,9 +114,37 @@ pub(crate) fn apply_bashlike(state: &ShellState) -> String {
   let set_str = state
     .set_var
     .iter()
-    .map(|(key, val)| format!("export {key}={val};"))
+    .map(|(key, val)| format!("export {key}={};", quote(val)))
     .collect::<Vec<_>>()
     .join("");
 
   format!("{unset_str}{set_str}{path_str}")
 }
+
+/// Quote a value for bash-like shells. Single quotes don't allow any expansion,
+/// so the only thing that needs escaping is a single quote itself.
+fn quote(val: &str) -> String {
+  format!("'{}'", val.replace('\'', r"'\''"))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn quote_values() {
+    assert_eq!(quote("foo"), "'foo'");
+    assert_eq!(quote("two words"), "'two words'");
+    assert_eq!(quote("$HOME"), "'$HOME'");
+    assert_eq!(quote("it's"), r"'it'\''s'");
+  }
+
+  #[test]
+  fn replace_path() {
+    let mut state = ShellState::new();
+    state.add_path("/ignored");
+    state.replace_path(vec!["/tool/bin".to_owned(), "/usr/bin".to_owned()]);
+
+    assert_eq!(apply_bashlike(&state), "export PATH='/tool/bin:/usr/bin';");
+  }
+}
@@ -0,0 +1,137 @@
+use std::{
+  collections::BTreeMap,
+  fs, io,
+  path::{Path, PathBuf},
+};
+
+use log::trace;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum TrustError {
+  #[error("io error while reading or writing trust store: {0}")]
+  Io(#[from] io::Error),
+
+  #[error("failed to parse trust store: {0}")]
+  Parse(#[from] toml::de::Error),
+
+  #[error("failed to serialize trust store: {0}")]
+  Serialize(#[from] toml::ser::Error),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrustStatus {
+  Trusted,
+  Untrusted,
+
+  /// The file was trusted, but its contents changed since then.
+  Changed,
+}
+
+/// Files the user explicitly allowed to modify their env, keyed by their canonical
+/// path, together with a hash of their contents at the time they were trusted.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct TrustStore {
+  #[serde(default)]
+  files: BTreeMap<String, String>,
+}
+
+impl TrustStore {
+  pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, TrustError> {
+    if !path.as_ref().is_file() {
+      return Ok(Self::default());
+    }
+
+    let contents = fs::read_to_string(path)?;
+    Ok(toml::from_str(&contents)?)
+  }
+
+  pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), TrustError> {
+    fs::write(path, toml::to_string(self)?)?;
+    Ok(())
+  }
+
+  pub fn trust<P: AsRef<Path>>(&mut self, file: P) -> Result<(), TrustError> {
+    let hash = hash_file(&file)?;
+    self.files.insert(store_key(file), hash);
+    Ok(())
+  }
+
+  /// Remove a file from the store, returning whether it was trusted before.
+  pub fn untrust<P: AsRef<Path>>(&mut self, file: P) -> bool {
+    self.files.remove(&store_key(file)).is_some()
+  }
+
+  /// The canonical paths of all files that were trusted at some point.
+  pub fn files(&self) -> impl Iterator<Item = PathBuf> + '_ {
+    self.files.keys().map(PathBuf::from)
+  }
+
+  pub fn status<P: AsRef<Path>>(&self, file: P) -> Result<TrustStatus, TrustError> {
+    let trusted_hash = match self.files.get(&store_key(&file)) {
+      Some(trusted_hash) => trusted_hash,
+      None => return Ok(TrustStatus::Untrusted),
+    };
+
+    if *trusted_hash == hash_file(&file)? {
+      Ok(TrustStatus::Trusted)
+    } else {
+      trace!("Hash for {:?} changed since it was trusted", file.as_ref());
+      Ok(TrustStatus::Changed)
+    }
+  }
+}
+
+fn store_key<P: AsRef<Path>>(file: P) -> String {
+  fs::canonicalize(&file)
+    .unwrap_or_else(|_| PathBuf::from(file.as_ref()))
+    .to_string_lossy()
+    .to_string()
+}
+
+fn hash_file<P: AsRef<Path>>(file: P) -> Result<String, io::Error> {
+  let contents = fs::read(file)?;
+  let hash = Sha256::digest(contents);
+  Ok(hash.iter().map(|byte| format!("{byte:02x}")).collect())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn trust_and_change() {
+    let workdir = tempfile::tempdir().expect("failed to create temp dir");
+    let file = workdir.as_ref().join(".tool-versions");
+    fs::write(&file, "foo 1").expect("failed to write file");
+
+    let mut store = TrustStore::default();
+    assert_eq!(store.status(&file).unwrap(), TrustStatus::Untrusted);
+
+    store.trust(&file).expect("failed to trust file");
+    assert_eq!(store.status(&file).unwrap(), TrustStatus::Trusted);
+
+    fs::write(&file, "foo 2").expect("failed to write file");
+    assert_eq!(store.status(&file).unwrap(), TrustStatus::Changed);
+
+    assert!(store.untrust(&file));
+    assert_eq!(store.status(&file).unwrap(), TrustStatus::Untrusted);
+  }
+
+  #[test]
+  fn roundtrip() {
+    let workdir = tempfile::tempdir().expect("failed to create temp dir");
+    let file = workdir.as_ref().join(".env");
+    let store_path = workdir.as_ref().join("trust.toml");
+    fs::write(&file, "FOO=bar").expect("failed to write file");
+
+    let mut store = TrustStore::default();
+    store.trust(&file).expect("failed to trust file");
+    store.save(&store_path).expect("failed to save store");
+
+    let loaded = TrustStore::load(&store_path).expect("failed to load store");
+    assert_eq!(loaded.status(&file).unwrap(), TrustStatus::Trusted);
+  }
+}
@@ -1,12 +1,18 @@
+use clap::ArgEnum;
 use log::trace;
+use serde::Serialize;
 use std::{
+  cmp::Ordering,
   collections::HashMap,
-  fs, io,
+  fmt, fs, io,
   ops::{Deref, DerefMut},
   path::{Path, PathBuf},
 };
 use thiserror::Error;
 
+const QWER_CEILING_DIRECTORIES: &str = "QWER_CEILING_DIRECTORIES";
+const QWER_STOP_AT_REPO_ROOT: &str = "QWER_STOP_AT_REPO_ROOT";
+
 #[derive(Error, Debug)]
 pub enum VersionsError {
   #[error("no versions file could be found in the current or any parent directories")]
@@ -98,6 +104,206 @@ impl Version {
   }
 }
 
+/// How far apart two versions are, from the smallest to the largest step.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, ArgEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Bump {
+  Patch,
+  Minor,
+  Major,
+}
+
+impl fmt::Display for Bump {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Self::Patch => write!(f, "patch"),
+      Self::Minor => write!(f, "minor"),
+      Self::Major => write!(f, "major"),
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment<'a> {
+  Number(u64),
+  Text(&'a str),
+}
+
+/// Split a version into runs of digits and text, dropping separators.
+fn segments(version: &str) -> Vec<Segment<'_>> {
+  let mut result = Vec::new();
+  let mut rest = version;
+
+  while let Some(start) = rest.find(|c: char| !matches!(c, '.' | '-' | '_' | '+')) {
+    rest = &rest[start..];
+    let is_digit = rest.starts_with(|c: char| c.is_ascii_digit());
+    let end = rest
+      .find(|c: char| matches!(c, '.' | '-' | '_' | '+') || c.is_ascii_digit() != is_digit)
+      .unwrap_or(rest.len());
+
+    let (segment, next) = rest.split_at(end);
+    result.push(match segment.parse() {
+      Ok(num) if is_digit => Segment::Number(num),
+      _ => Segment::Text(segment),
+    });
+    rest = next;
+  }
+
+  result
+}
+
+/// Compare two version strings the way people read them. Runs of digits compare
+/// as numbers, so `1.10.0` comes after `1.9.2`, and a version with a text suffix
+/// like `1.0.0-rc1` comes before `1.0.0`.
+pub fn compare_versions(a: &str, b: &str) -> Ordering {
+  let (a, b) = (segments(a), segments(b));
+
+  for i in 0..a.len().max(b.len()) {
+    let ordering = match (a.get(i), b.get(i)) {
+      (Some(Segment::Number(a)), Some(Segment::Number(b))) => a.cmp(b),
+      (Some(Segment::Text(a)), Some(Segment::Text(b))) => a.cmp(b),
+      (Some(Segment::Number(_)), Some(Segment::Text(_))) => Ordering::Greater,
+      (Some(Segment::Text(_)), Some(Segment::Number(_))) => Ordering::Less,
+      // Versions that only have more numbers are newer, pre-release suffixes older
+      (Some(Segment::Number(_)), None) => Ordering::Greater,
+      (Some(Segment::Text(_)), None) => Ordering::Less,
+      (None, Some(Segment::Number(_))) => Ordering::Less,
+      (None, Some(Segment::Text(_))) => Ordering::Greater,
+      (None, None) => Ordering::Equal,
+    };
+
+    if ordering != Ordering::Equal {
+      return ordering;
+    }
+  }
+
+  Ordering::Equal
+}
+
+/// Find how big the step from `from` to the newer version `to` is. Leading text
+/// like `v` or `temurin-` has to match, and the numbers after it are read as
+/// major, minor and patch. Returns `None` if `to` isn't newer or the versions
+/// can't be compared.
+pub fn bump_between(from: &str, to: &str) -> Option<Bump> {
+  if compare_versions(from, to) != Ordering::Less {
+    return None;
+  }
+
+  let (from, to) = (segments(from), segments(to));
+  let prefix = |segments: &[Segment]| {
+    segments
+      .iter()
+      .take_while(|segment| matches!(segment, Segment::Text(_)))
+      .count()
+  };
+
+  let (from_prefix, to_prefix) = (prefix(&from), prefix(&to));
+  if from[..from_prefix] != to[..to_prefix] {
+    return None;
+  }
+
+  let numbers = |segments: &[Segment]| {
+    segments
+      .iter()
+      .map_while(|segment| match segment {
+        Segment::Number(num) => Some(*num),
+        Segment::Text(_) => None,
+      })
+      .collect::<Vec<_>>()
+  };
+
+  let (from, to) = (numbers(&from[from_prefix..]), numbers(&to[to_prefix..]));
+  match (0..2).find(|&i| from.get(i) != to.get(i)) {
+    Some(0) => Some(Bump::Major),
+    Some(_) => Some(Bump::Minor),
+    None => Some(Bump::Patch),
+  }
+}
+
+/// Controls how far the directory walk for versions files goes.
+#[derive(Debug, Clone, Default)]
+pub struct WalkOptions {
+  /// Directories the walk will not move up into. The directory the walk
+  /// starts in is always checked, even if it is a ceiling directory itself.
+  pub ceiling_directories: Vec<PathBuf>,
+
+  /// Stop after the first directory that contains a `.git` entry.
+  pub stop_at_repo_root: bool,
+
+  /// Files that are always included after the walk, at the lowest precedence,
+  /// unless the walk already found them.
+  pub global_files: Vec<PathBuf>,
+}
+
+impl WalkOptions {
+  /// Read the walk options from `QWER_CEILING_DIRECTORIES`, a colon-separated list
+  /// of absolute paths, and `QWER_STOP_AT_REPO_ROOT`. Relative ceiling directories
+  /// are ignored, same as git does for `GIT_CEILING_DIRECTORIES`.
+  pub fn from_env() -> Self {
+    let ceiling_directories = std::env::var_os(QWER_CEILING_DIRECTORIES)
+      .map(|dirs| {
+        std::env::split_paths(&dirs)
+          .filter(|dir| dir.is_absolute())
+          .collect()
+      })
+      .unwrap_or_default();
+
+    let stop_at_repo_root = std::env::var(QWER_STOP_AT_REPO_ROOT)
+      .map(|val| matches!(val.as_str(), "1" | "true" | "yes"))
+      .unwrap_or(false);
+
+    Self {
+      ceiling_directories,
+      stop_at_repo_root,
+      global_files: Vec::new(),
+    }
+  }
+}
+
+/// A single parsed line of a versions file, mapping a plugin to its version options.
+pub type Entry = (String, Vec<Version>);
+
+/// A tool from a versions file, together with the file and line it was defined on.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToolEntry {
+  pub plugin: String,
+  pub versions: Vec<Version>,
+  pub source: PathBuf,
+  pub line: usize,
+}
+
+impl ToolEntry {
+  /// A tool pinned by its override var instead of a versions file. The source of
+  /// the entry is the name of the var, and its line is 0.
+  pub fn from_override(plugin: &str, versions: Vec<Version>) -> Self {
+    Self {
+      plugin: plugin.to_owned(),
+      versions,
+      source: PathBuf::from(version_override_var(plugin)),
+      line: 0,
+    }
+  }
+
+  pub fn is_override(&self) -> bool {
+    self.line == 0
+  }
+
+  /// Where the tool was pinned, either `file:line` or the name of the override var.
+  pub fn location(&self) -> String {
+    if self.is_override() {
+      self.source.to_string_lossy().to_string()
+    } else {
+      format!("{}:{}", self.source.to_string_lossy(), self.line)
+    }
+  }
+}
+
+/// The var that overrides the versions files for `plugin`, named like in asdf,
+/// e.g. `ASDF_NODEJS_VERSION`.
+pub fn version_override_var(plugin: &str) -> String {
+  format!("ASDF_{}_VERSION", plugin.to_uppercase().replace('-', "_"))
+}
+
 #[derive(Debug, Clone, Default)]
 pub struct Versions(HashMap<String, Vec<Version>>);
 
@@ -119,42 +325,53 @@ impl Versions {
   pub fn parse(content: &str) -> Result<Self, VersionsError> {
     trace!("Parsing versions:\n{content}");
 
-    let lines = content
-      .split('\n')
-      .map(|line| line.trim())
-      // Filter out comments
-      .filter(|line| !line.starts_with('#') && !line.is_empty())
-      // Remove comments from line ends, and trim the end
-      // again to remove trailing whitespaces
-      .map(|line| line.split('#').next().unwrap().trim())
-      .collect::<Vec<_>>();
-
+    let lines = Self::parse_lines(content);
     let mut result = Versions(HashMap::with_capacity(lines.len()));
-    for line in lines {
-      let parts = line.split(' ').collect::<Vec<_>>();
-      if parts.len() <= 1 {
-        return Err(VersionsError::InvalidEntry(line.to_owned()));
+    for (_, entry) in lines {
+      let (plugin, versions) = entry?;
+      if result.0.contains_key(&plugin) {
+        return Err(VersionsError::DuplicateEntry(plugin));
       }
 
-      if result.0.contains_key(parts[0]) {
-        return Err(VersionsError::DuplicateEntry(parts[0].to_owned()));
-      }
-
-      let versions = parts
-        .iter()
-        .skip(1)
-        .map(|version| Version::parse(version))
-        .collect::<Vec<_>>();
-
-      result.0.insert(parts[0].to_owned(), versions);
+      result.0.insert(plugin, versions);
     }
 
     Ok(result)
   }
 
+  /// Parse every line of a versions file on its own, without stopping at the first
+  /// invalid entry. Comments and empty lines are skipped, and each result comes with
+  /// the 1-based line number it was parsed from. Duplicate entries are not detected here.
+  pub fn parse_lines(content: &str) -> Vec<(usize, Result<Entry, VersionsError>)> {
+    content
+      .split('\n')
+      .enumerate()
+      .map(|(i, line)| (i + 1, line.trim()))
+      // Filter out comments
+      .filter(|(_, line)| !line.starts_with('#') && !line.is_empty())
+      // Remove comments from line ends, and trim the end
+      // again to remove trailing whitespaces
+      .map(|(i, line)| (i, line.split('#').next().unwrap().trim()))
+      .map(|(i, line)| {
+        let parts = line.split(' ').collect::<Vec<_>>();
+        if parts.len() <= 1 {
+          return (i, Err(VersionsError::InvalidEntry(line.to_owned())));
+        }
+
+        let versions = parts
+          .iter()
+          .skip(1)
+          .map(|version| Version::parse(version))
+          .collect::<Vec<_>>();
+
+        (i, Ok((parts[0].to_owned(), versions)))
+      })
+      .collect()
+  }
+
   /// Find a file in the local directory and parse it into a versions map.
   /// and parse it into a versions map.
-  pub fn find<P: AsRef<Path>>(workdir: P, filename: &str) -> Result<Self, VersionsError> {
+  pub fn _find<P: AsRef<Path>>(workdir: P, filename: &str) -> Result<Self, VersionsError> {
     let versions_file_path = workdir.as_ref().join(filename);
     trace!("Looking for versions file at `{:?}`", versions_file_path);
     let versions_content = fs::read_to_string(versions_file_path)?;
@@ -163,8 +380,12 @@ impl Versions {
 
   /// Walk the directory tree upwards until a file with the given filename is found,
   /// and parse it into a versions map.
-  pub fn _find_any<P: AsRef<Path>>(workdir: P, filename: &str) -> Result<Self, VersionsError> {
-    let versions_file_path = _find_versions_file(workdir, filename)?;
+  pub fn _find_any<P: AsRef<Path>>(
+    workdir: P,
+    filename: &str,
+    options: &WalkOptions,
+  ) -> Result<Self, VersionsError> {
+    let versions_file_path = _find_versions_file(workdir, filename, options)?;
     let versions_content = fs::read_to_string(versions_file_path)?;
     Self::parse(&versions_content)
   }
@@ -172,18 +393,103 @@ impl Versions {
   /// Continually walk the directory tree upwards and find all version files, parsing
   /// all of them into version maps. The returned results will be in the order the
   /// files were found in.
-  pub fn find_all<P: AsRef<Path>>(workdir: P, filename: &str) -> Result<Vec<Self>, VersionsError> {
-    let versions_file_paths = find_all_versions_files(workdir, filename)?;
+  pub fn find_all<P: AsRef<Path>>(
+    workdir: P,
+    filename: &str,
+    options: &WalkOptions,
+  ) -> Result<Vec<Self>, VersionsError> {
+    Ok(
+      Self::find_all_with_paths(workdir, filename, options)?
+        .into_iter()
+        .map(|(_, versions)| versions)
+        .collect(),
+    )
+  }
+
+  /// Same as [`Versions::find_all`], but also returns the path each versions map
+  /// was parsed from.
+  pub fn find_all_with_paths<P: AsRef<Path>>(
+    workdir: P,
+    filename: &str,
+    options: &WalkOptions,
+  ) -> Result<Vec<(PathBuf, Self)>, VersionsError> {
+    let versions_file_paths = find_all_versions_files(workdir, filename, options)?;
 
     versions_file_paths
-      .iter()
-      .map(fs::read_to_string)
-      .collect::<Result<Vec<_>, _>>()?
-      .iter()
-      .map(|content| Self::parse(content))
+      .into_iter()
+      .map(|path| {
+        let content = fs::read_to_string(&path)?;
+        Ok((path, Self::parse(&content)?))
+      })
       .collect()
   }
 
+  /// Walk the directory tree upwards and merge all found files into a single list of
+  /// tools in order of precedence. Closer files come first, and tools within a file
+  /// keep the order of their lines. A tool defined in more than one file only appears
+  /// once, with the entry from the closest file.
+  pub fn find_toolset<P: AsRef<Path>>(
+    workdir: P,
+    filename: &str,
+    options: &WalkOptions,
+  ) -> Result<Vec<ToolEntry>, VersionsError> {
+    Ok(dedupe_entries(Self::find_all_entries(
+      workdir, filename, options,
+    )?))
+  }
+
+  /// Same as [`Versions::find_toolset`], but for a fixed list of files instead of a
+  /// directory walk. Earlier files take precedence.
+  pub fn toolset_from_files(paths: &[PathBuf]) -> Result<Vec<ToolEntry>, VersionsError> {
+    Ok(dedupe_entries(Self::entries_from_files(paths)?))
+  }
+
+  /// Same as [`Versions::find_toolset`], but tools defined in more than one file
+  /// appear once for every file.
+  pub fn find_all_entries<P: AsRef<Path>>(
+    workdir: P,
+    filename: &str,
+    options: &WalkOptions,
+  ) -> Result<Vec<ToolEntry>, VersionsError> {
+    let versions_file_paths = find_all_versions_files(workdir, filename, options)?;
+    Self::entries_from_files(&versions_file_paths)
+  }
+
+  /// Parse the entries of every file in `paths`, in order.
+  pub fn entries_from_files(paths: &[PathBuf]) -> Result<Vec<ToolEntry>, VersionsError> {
+    let mut result = Vec::<ToolEntry>::new();
+
+    for path in paths {
+      let content = fs::read_to_string(path)?;
+
+      // Parse the whole file first, so invalid files are rejected the same
+      // way as everywhere else.
+      Self::parse(&content)?;
+
+      for (line, entry) in Self::parse_lines(&content) {
+        let (plugin, versions) = entry?;
+        result.push(ToolEntry {
+          plugin,
+          versions,
+          source: path.to_owned(),
+          line,
+        });
+      }
+    }
+
+    Ok(result)
+  }
+
+  /// Walk the directory tree upwards the same way as [`Versions::find_all`], but only
+  /// return the paths of the files that were found.
+  pub fn find_all_files<P: AsRef<Path>>(
+    workdir: P,
+    filename: &str,
+    options: &WalkOptions,
+  ) -> Result<Vec<PathBuf>, VersionsError> {
+    find_all_versions_files(workdir, filename, options)
+  }
+
   pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), VersionsError> {
     let contents = self
       .iter()
@@ -221,33 +527,84 @@ impl DerefMut for Versions {
   }
 }
 
-fn find_all_versions_files<P: AsRef<Path>>(
+/// Keep the first entry for every plugin.
+fn dedupe_entries(entries: Vec<ToolEntry>) -> Vec<ToolEntry> {
+  let mut result = Vec::<ToolEntry>::new();
+
+  for entry in entries {
+    if !result
+      .iter()
+      .any(|existing| existing.plugin == entry.plugin)
+    {
+      result.push(entry);
+    }
+  }
+
+  result
+}
+
+/// Walk the directory tree upwards starting at `workdir`, calling `visit` for every
+/// directory until it returns `false` or the walk is stopped by the given options.
+fn walk_up<P: AsRef<Path>>(
   workdir: P,
-  filename: &str,
-) -> Result<Vec<PathBuf>, VersionsError> {
+  options: &WalkOptions,
+  mut visit: impl FnMut(&Path) -> bool,
+) -> Result<(), VersionsError> {
   let mut current_dir = workdir.as_ref();
   if !current_dir.is_dir() {
     return Err(VersionsError::InvalidWorkdir);
   }
 
-  let mut result = Vec::new();
   loop {
-    trace!("Looking for versions file in {:?}", current_dir);
+    if !visit(current_dir) {
+      return Ok(());
+    }
 
-    let files = fs::read_dir(&current_dir)?;
-    for file in files {
-      let file = file?;
-      if file.file_name() == filename {
-        result.push(file.path());
-      }
+    if options.stop_at_repo_root && current_dir.join(".git").exists() {
+      trace!("Stopping at repository root {:?}", current_dir);
+      return Ok(());
     }
 
-    let next_dir = current_dir.parent();
-    if next_dir.is_none() {
-      break;
+    let next_dir = match current_dir.parent() {
+      Some(next_dir) => next_dir,
+      None => return Ok(()),
+    };
+
+    if options
+      .ceiling_directories
+      .iter()
+      .any(|dir| dir == next_dir)
+    {
+      trace!("Stopping at ceiling directory {:?}", next_dir);
+      return Ok(());
     }
 
-    current_dir = next_dir.unwrap();
+    current_dir = next_dir;
+  }
+}
+
+fn find_all_versions_files<P: AsRef<Path>>(
+  workdir: P,
+  filename: &str,
+  options: &WalkOptions,
+) -> Result<Vec<PathBuf>, VersionsError> {
+  let mut result = Vec::new();
+  walk_up(workdir, options, |dir| {
+    trace!("Looking for versions file in {:?}", dir);
+
+    let candidate = dir.join(filename);
+    if candidate.is_file() {
+      result.push(candidate);
+    }
+
+    true
+  })?;
+
+  for global_file in &options.global_files {
+    if global_file.is_file() && !result.contains(global_file) {
+      trace!("Adding global versions file {:?}", global_file);
+      result.push(global_file.clone());
+    }
   }
 
   Ok(result)
@@ -256,27 +613,22 @@ fn find_all_versions_files<P: AsRef<Path>>(
 fn _find_versions_file<P: AsRef<Path>>(
   workdir: P,
   filename: &str,
+  options: &WalkOptions,
 ) -> Result<PathBuf, VersionsError> {
-  let mut current_dir = workdir.as_ref();
-  if !current_dir.is_dir() {
-    return Err(VersionsError::InvalidWorkdir);
-  }
-
-  loop {
-    trace!("Looking for versions file in {:?}", current_dir);
-
-    let files = fs::read_dir(&current_dir)?;
-    for file in files {
-      let file = file?;
-      if file.file_name() == filename {
-        return Ok(file.path());
-      }
+  let mut result = None;
+  walk_up(workdir, options, |dir| {
+    trace!("Looking for versions file in {:?}", dir);
+
+    let candidate = dir.join(filename);
+    if candidate.is_file() {
+      result = Some(candidate);
+      return false;
     }
 
-    current_dir = current_dir
-      .parent()
-      .ok_or(VersionsError::_NoVersionsFound)?
-  }
+    true
+  })?;
+
+  result.ok_or(VersionsError::_NoVersionsFound)
 }
 
 #[cfg(test)]
@@ -337,7 +689,8 @@ foo 2.1
     let workdir = tempfile::tempdir().expect("failed to create temp dir");
     fs::write(workdir.as_ref().join("v"), "foo 1").expect("failed to write versions");
 
-    let versions = Versions::_find_any(workdir.as_ref(), "v").expect("failed to find versions");
+    let versions = Versions::_find_any(workdir.as_ref(), "v", &WalkOptions::default())
+      .expect("failed to find versions");
     assert_eq!(versions["foo"], &[Version::Remote("1".to_owned())]);
   }
 
@@ -346,7 +699,7 @@ foo 2.1
     let workdir = tempfile::tempdir().expect("failed to create temp dir");
     let subdir = workdir.as_ref().join("foo/bar/baz");
     fs::create_dir_all(&subdir).expect("failed to create dirs");
-    let result = Versions::_find_any(subdir, "v");
+    let result = Versions::_find_any(subdir, "v", &WalkOptions::default());
     assert!(matches!(result, Err(VersionsError::_NoVersionsFound)));
   }
 
@@ -354,7 +707,7 @@ foo 2.1
   fn no_dir() {
     let workdir = tempfile::tempdir().expect("failed to create temp dir");
     let subdir = workdir.as_ref().join("foo/bar/baz");
-    let result = Versions::_find_any(subdir, "v");
+    let result = Versions::_find_any(subdir, "v", &WalkOptions::default());
     assert!(matches!(result, Err(VersionsError::InvalidWorkdir)));
   }
 
@@ -365,7 +718,166 @@ foo 2.1
     fs::create_dir_all(&subdir).expect("failed to create dirs");
     fs::write(workdir.as_ref().join("v"), "foo 1").expect("failed to write versions");
 
-    let versions = Versions::_find_any(subdir, "v").expect("failed to find versions");
+    let versions =
+      Versions::_find_any(subdir, "v", &WalkOptions::default()).expect("failed to find versions");
     assert_eq!(versions["foo"], &[Version::Remote("1".to_owned())]);
   }
+
+  #[test]
+  fn find_all_ceiling_dir() {
+    let workdir = tempfile::tempdir().expect("failed to create temp dir");
+    let ceiling = workdir.as_ref().join("foo");
+    let subdir = ceiling.join("bar/baz");
+    fs::create_dir_all(&subdir).expect("failed to create dirs");
+    fs::write(workdir.as_ref().join("v"), "foo 1").expect("failed to write versions");
+    fs::write(ceiling.join("v"), "foo 2").expect("failed to write versions");
+    fs::write(ceiling.join("bar/v"), "foo 3").expect("failed to write versions");
+    fs::write(subdir.join("v"), "foo 4").expect("failed to write versions");
+
+    let options = WalkOptions {
+      ceiling_directories: vec![ceiling],
+      ..Default::default()
+    };
+
+    let versions = Versions::find_all(&subdir, "v", &options).expect("failed to find versions");
+    assert_eq!(versions.len(), 2);
+    assert_eq!(versions[0]["foo"], &[Version::Remote("4".to_owned())]);
+    assert_eq!(versions[1]["foo"], &[Version::Remote("3".to_owned())]);
+  }
+
+  #[test]
+  fn find_all_stop_at_repo_root() {
+    let workdir = tempfile::tempdir().expect("failed to create temp dir");
+    let repo = workdir.as_ref().join("repo");
+    let subdir = repo.join("foo");
+    fs::create_dir_all(&subdir).expect("failed to create dirs");
+    fs::create_dir_all(repo.join(".git")).expect("failed to create git dir");
+    fs::write(workdir.as_ref().join("v"), "foo 1").expect("failed to write versions");
+    fs::write(subdir.join("v"), "foo 2").expect("failed to write versions");
+
+    let options = WalkOptions {
+      stop_at_repo_root: true,
+      ..Default::default()
+    };
+
+    let versions = Versions::find_all(&subdir, "v", &options).expect("failed to find versions");
+    assert_eq!(versions.len(), 1);
+    assert_eq!(versions[0]["foo"], &[Version::Remote("2".to_owned())]);
+  }
+
+  #[test]
+  fn find_all_global_files() {
+    let workdir = tempfile::tempdir().expect("failed to create temp dir");
+    let subdir = workdir.as_ref().join("foo");
+    let global_dir = tempfile::tempdir().expect("failed to create temp dir");
+    let global_file = global_dir.as_ref().join("global");
+    fs::create_dir_all(&subdir).expect("failed to create dirs");
+    fs::write(subdir.join("v"), "foo 1").expect("failed to write versions");
+    fs::write(&global_file, "foo 2\nbar 3").expect("failed to write versions");
+
+    let options = WalkOptions {
+      global_files: vec![global_file, workdir.as_ref().join("missing")],
+      ..Default::default()
+    };
+
+    let versions = Versions::find_all(&subdir, "v", &options).expect("failed to find versions");
+    assert_eq!(versions.len(), 2);
+    assert_eq!(versions[0]["foo"], &[Version::Remote("1".to_owned())]);
+    assert_eq!(versions[1]["bar"], &[Version::Remote("3".to_owned())]);
+  }
+
+  #[test]
+  fn parse_lines() {
+    let to_parse = r#"
+# comment
+foo 1.2.3
+invalid
+
+foo 2.1 # comment
+        "#;
+
+    let lines = Versions::parse_lines(to_parse);
+    assert_eq!(lines.len(), 3);
+
+    assert_eq!(lines[0].0, 3);
+    assert!(matches!(&lines[0].1, Ok((plugin, _)) if plugin == "foo"));
+
+    assert_eq!(lines[1].0, 4);
+    assert!(matches!(lines[1].1, Err(VersionsError::InvalidEntry(_))));
+
+    assert_eq!(lines[2].0, 6);
+    assert!(
+      matches!(&lines[2].1, Ok((_, versions)) if versions == &[Version::Remote("2.1".to_owned())])
+    );
+  }
+
+  #[test]
+  fn find_toolset_order() {
+    let workdir = tempfile::tempdir().expect("failed to create temp dir");
+    let subdir = workdir.as_ref().join("foo");
+    fs::create_dir_all(&subdir).expect("failed to create dirs");
+    fs::write(workdir.as_ref().join("v"), "b 1\nz 1\na 1").expect("failed to write versions");
+    fs::write(subdir.join("v"), "# comment\nz 2\nb 2").expect("failed to write versions");
+
+    let toolset = Versions::find_toolset(&subdir, "v", &WalkOptions::default())
+      .expect("failed to find toolset");
+
+    let plugins = toolset
+      .iter()
+      .map(|entry| (entry.plugin.as_str(), entry.line))
+      .collect::<Vec<_>>();
+
+    assert_eq!(plugins, vec![("z", 2), ("b", 3), ("a", 3)]);
+    assert_eq!(toolset[0].versions, &[Version::Remote("2".to_owned())]);
+    assert_eq!(toolset[2].source, workdir.as_ref().join("v"));
+
+    let all_entries = Versions::find_all_entries(&subdir, "v", &WalkOptions::default())
+      .expect("failed to find entries");
+    assert_eq!(all_entries.len(), 5);
+    assert_eq!(all_entries[3].plugin, "z");
+    assert_eq!(all_entries[3].source, workdir.as_ref().join("v"));
+  }
+
+  #[test]
+  fn version_ordering() {
+    let mut versions = vec![
+      "1.10.0",
+      "1.9.2",
+      "1.0.0",
+      "1.0.0-rc1",
+      "1.0",
+      "2.0.0-beta.2",
+      "2.0.0-beta.10",
+    ];
+    versions.sort_by(|a, b| compare_versions(a, b));
+
+    assert_eq!(
+      versions,
+      vec![
+        "1.0",
+        "1.0.0-rc1",
+        "1.0.0",
+        "1.9.2",
+        "1.10.0",
+        "2.0.0-beta.2",
+        "2.0.0-beta.10",
+      ]
+    );
+  }
+
+  #[test]
+  fn bumps() {
+    assert_eq!(bump_between("18.11.0", "18.11.1"), Some(Bump::Patch));
+    assert_eq!(bump_between("18.11.0", "18.12.0"), Some(Bump::Minor));
+    assert_eq!(bump_between("18.11.0", "20.0.0"), Some(Bump::Major));
+    assert_eq!(bump_between("v1.2", "v1.2.1"), Some(Bump::Patch));
+    assert_eq!(
+      bump_between("temurin-17.0.2+8", "temurin-17.0.4+1"),
+      Some(Bump::Patch)
+    );
+    assert_eq!(bump_between("3.10.0-rc1", "3.10.0"), Some(Bump::Patch));
+    assert_eq!(bump_between("18.11.0", "18.11.0"), None);
+    assert_eq!(bump_between("18.11.0", "16.0.0"), None);
+    assert_eq!(bump_between("temurin-17.0.2", "zulu-18.0.0"), None);
+  }
 }